@@ -56,7 +56,7 @@
                 type = types.int;
               };
               unit = mkOption {
-                type = types.enum [ "minutes" "hours" "days" "weeks" ];
+                type = types.enum [ "minutes" "hours" "days" "weeks" "months" "years" ];
               };
             };
           };
@@ -72,10 +72,12 @@
                 "snapshot_suffix": "${cfg.snapshot_suffix}",
                 "user_local": "${cfg.user_local}",
                 "policy_local": ['' + (lib.strings.concatStringsSep ", " (lib.map (elem: "{ \"${elem.unit}\": ${builtins.toString elem.value} }") cfg.policy_local)) + ''],
+                '' + (lib.optionalString (cfg.ssh_host != null) (''
                 "config_ssh": {
-                  "host": "${cfg.ssh_host}",
-                  "config": "${cfg.ssh_config}"
+                  "host": "${cfg.ssh_host}"'' + (lib.optionalString (cfg.ssh_config != null) '',
+                  "config": "${cfg.ssh_config}"'') + ''
                 },
+                '')) + ''
                 "backup_device": "${cfg.backup_device}",
                 "backup_subvolume_path": "${cfg.backup_subvolume_path}",
                 "backup_path": "${cfg.backup_path}",
@@ -132,12 +134,14 @@
               type = types.listOf (types.submodule policyOptions);
             };
             ssh_host = mkOption {
-              type = types.str;
-              description = lib.mdDoc "name of the remote host";
+              type = types.nullOr types.str;
+              default = null;
+              description = lib.mdDoc "name of the remote host; if null, the snapshots are sent to a filesystem on the local host";
             };
             ssh_config = mkOption {
-              type = types.path;
-              description = lib.mdDoc "path of the ssh configuration file";
+              type = types.nullOr types.path;
+              default = null;
+              description = lib.mdDoc "path of the ssh configuration file; if null, the default configuration of ssh is used (only used with ssh_host)";
             };
             backup_device = mkOption {
               type = types.path;
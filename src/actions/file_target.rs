@@ -0,0 +1,571 @@
+use super::ActionsSystem;
+use crate::backup_error::BackupError;
+use crate::commands::Commands;
+use crate::objects::*;
+use crate::retention::RetentionPolicy;
+use chrono::{DateTime, FixedOffset, Utc};
+use exec_rs::Context;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Actions for file targets, which store the send streams of the snapshots as files described by a manifest (see `StreamManifest`)
+pub trait ActionsFileTarget {
+    /// Send a snapshot to a file target
+    ///
+    /// The stream is incremental to the newest snapshot stored on the target, unless the chain of incremental streams would exceed the maximum length.
+    /// Returns the path and the transfer statistics of the snapshot, if statistics were reported.
+    ///
+    /// * `parent_subvolume_path` - path of the subvolume the snapshots were taken of
+    /// * `local_device` - path of the local device and links to it
+    /// * `local_subvolume_path` - path of the subvolume containing the local snapshots
+    /// * `local_mount_information` - local mount information
+    /// * `snapshot` - snapshot to be sent
+    /// * `context_local` - context for executing local commands
+    /// * `target_path` - directory of the file target
+    /// * `context_target` - context for executing commands on the target
+    /// * `max_chain_length` - maximum number of streams in a chain starting with a full stream
+    /// * `options` - transfer options (the required programs are checked before the transfer starts)
+    fn send_snapshot_to_file(
+        &mut self,
+        parent_subvolume_path: &str,
+        local_device: &[String],
+        local_subvolume_path: &str,
+        local_mount_information: &Vec<MountInformation>,
+        snapshot: &SubvolumeInfo,
+        context_local: &Context,
+        target_path: &str,
+        context_target: &Context,
+        max_chain_length: usize,
+        options: &TransferOptions,
+    ) -> Result<Vec<(String, TransferStats)>, BackupError>;
+    /// Get the manifest of a file target
+    ///
+    /// * `target_path` - directory of the file target
+    /// * `context` - context of the target
+    fn get_stream_manifest(
+        &mut self,
+        target_path: &str,
+        context: &Context,
+    ) -> Result<StreamManifest, BackupError>;
+    /// Delete the incomplete stream files left behind by interrupted transfers
+    ///
+    /// * `target_path` - directory of the file target
+    /// * `context` - context of the target
+    fn delete_incomplete_streams(
+        &mut self,
+        target_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+    /// Check whether the stream of a snapshot is recorded in the manifest of a file target
+    ///
+    /// * `target_path` - directory of the file target
+    /// * `snapshot` - snapshot that was sent
+    /// * `context` - context of the target
+    fn confirm_stream(
+        &mut self,
+        target_path: &str,
+        snapshot: &SubvolumeInfo,
+        context: &Context,
+    ) -> Result<bool, BackupError>;
+    /// Find the newest local snapshot stored on a file target
+    ///
+    /// * `parent_subvolume_path` - path of the subvolume the snapshots were taken of
+    /// * `local_subvolume_path` - path of the subvolume containing the local snapshots
+    /// * `context_local` - context for executing local commands
+    /// * `target_path` - directory of the file target
+    /// * `context_target` - context of the target
+    fn find_stream_common_parent(
+        &mut self,
+        parent_subvolume_path: &str,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        target_path: &str,
+        context_target: &Context,
+    ) -> Result<Option<Subvolume>, BackupError>;
+    /// Get the snapshots stored on a file target ordered from oldest to newest
+    ///
+    /// * `target_path` - directory of the file target
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `context` - context of the target
+    fn get_stream_snapshots(
+        &mut self,
+        target_path: &str,
+        suffix: &str,
+        context: &Context,
+    ) -> Result<Vec<(DateTime<Utc>, Subvolume)>, BackupError>;
+    /// Decide for every snapshot stored on a file target whether it is kept or deleted
+    ///
+    /// Streams, on which a retained stream depends, are retained as well.
+    ///
+    /// * `target_path` - directory of the file target
+    /// * `context` - context of the target
+    /// * `latest_local_snapshot` - latest local snapshot (will be kept)
+    /// * `common_parents` - uuids of the newest snapshots shared between the local host and the targets (will be kept)
+    /// * `policy` - policy to be applied
+    /// * `timestamp` - timestamp to use as the current moment
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `override_guards` - ignore the `min_keep` guard of the policy
+    fn plan_stream_retention(
+        &mut self,
+        target_path: &str,
+        context: &Context,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        suffix: &str,
+        override_guards: bool,
+    ) -> Result<Vec<RetentionDecision>, BackupError>;
+    /// Police the snapshots stored on a file target
+    ///
+    /// The manifest is updated before the stream files are deleted, so that it never refers to missing files.
+    /// Returns the paths of the deleted stream files.
+    ///
+    /// * `target_path` - directory of the file target
+    /// * `context_local` - context for executing local commands
+    /// * `context` - context of the target
+    /// * `latest_local_snapshot` - latest local snapshot (will be kept)
+    /// * `common_parents` - uuids of the newest snapshots shared between the local host and the targets (will be kept)
+    /// * `policy` - policy to be applied
+    /// * `timestamp` - timestamp to use as the current moment
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `override_guards` - ignore the `min_keep` and `max_delete_per_run` guards of the policy
+    fn police_streams(
+        &mut self,
+        target_path: &str,
+        context_local: &Context,
+        context: &Context,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        suffix: &str,
+        override_guards: bool,
+    ) -> Result<Vec<String>, BackupError>;
+}
+
+impl<C: Commands> ActionsFileTarget for ActionsSystem<C> {
+    fn send_snapshot_to_file(
+        &mut self,
+        parent_subvolume_path: &str,
+        local_device: &[String],
+        local_subvolume_path: &str,
+        local_mount_information: &Vec<MountInformation>,
+        snapshot: &SubvolumeInfo,
+        context_local: &Context,
+        target_path: &str,
+        context_target: &Context,
+        max_chain_length: usize,
+        options: &TransferOptions,
+    ) -> Result<Vec<(String, TransferStats)>, BackupError> {
+        // all programs of the pipeline run on the local host
+        if let Some(compression) = &options.compression {
+            self.commander
+                .check_program(compression.program(), context_local)?;
+        }
+
+        if let Some(encryption) = &options.encryption {
+            self.commander
+                .check_program(encryption.tool.program(), context_local)?;
+        }
+
+        if options.buffer_args().is_some() {
+            self.commander
+                .check_program(TransferOptions::BUFFER_PROGRAM, context_local)?;
+        }
+
+        let mut manifest = self.get_stream_manifest(target_path, context_target)?;
+        let common_parent = self
+            .find_stream_common_parent(
+                parent_subvolume_path,
+                local_subvolume_path,
+                context_local,
+                target_path,
+                context_target,
+            )?
+            // a broken or too long chain is restarted with a full stream
+            .filter(|sv| {
+                manifest
+                    .chain(&sv.uuid)
+                    .is_some_and(|chain| chain.len() < max_chain_length)
+            });
+        let parent = common_parent
+            .as_ref()
+            .map(|sv| {
+                Ok::<SubvolumeInfo, BackupError>(SubvolumeInfo {
+                    fs_path: ActionsSystem::<C>::btrfs_to_fs_path(
+                        local_mount_information,
+                        local_device,
+                        &sv.btrfs_path,
+                    )?,
+                    btrfs_path: sv.btrfs_path.clone(),
+                    uuid: sv.uuid,
+                })
+            })
+            .transpose()?;
+        let mut file = format!(
+            "{}.btrfs",
+            snapshot
+                .btrfs_path
+                .rsplit('/')
+                .next()
+                .unwrap_or(&snapshot.btrfs_path)
+        );
+
+        if let Some(compression) = &options.compression {
+            file = format!("{}.{}", file, compression.algorithm.extension());
+        }
+
+        if let Some(encryption) = &options.encryption {
+            file = format!("{}.{}", file, encryption.tool.extension());
+        }
+
+        match &parent {
+            Some(s) => log::info!(
+                snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid, parent = s.fs_path.as_str(), file = file.as_str();
+                "sending snapshot \"{}\" incrementally to file \"{}\"; found common parent snapshot \"{}\"",
+                snapshot.fs_path,
+                file,
+                &s.fs_path
+            ),
+            None => log::info!(
+                snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid, file = file.as_str();
+                "sending snapshot \"{}\" completely to file \"{}\"",
+                snapshot.fs_path,
+                file
+            ),
+        }
+
+        let stats = self.commander.send_snapshot_to_file(
+            snapshot,
+            parent.as_ref(),
+            context_local,
+            &format!("{}/{}", target_path.trim_end_matches('/'), file),
+            context_target,
+            options,
+        )?;
+
+        manifest.add(StreamEntry {
+            snapshot: snapshot.btrfs_path.clone(),
+            uuid: snapshot.uuid,
+            parent_uuid: parent.map(|p| p.uuid),
+            file,
+            compression: options.compression.as_ref().map(|c| c.algorithm),
+            encryption: options.encryption.as_ref().map(|e| e.tool),
+        });
+        self.commander
+            .write_manifest(target_path, &manifest, context_local, context_target)?;
+
+        Ok(stats
+            .map(|stats| {
+                log::info!(
+                    snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid, bytes = stats.bytes, duration = stats.seconds;
+                    "sent snapshot \"{}\": {}", snapshot.fs_path, stats
+                );
+                vec![(snapshot.fs_path.clone(), stats)]
+            })
+            .unwrap_or_default())
+    }
+
+    fn get_stream_manifest(
+        &mut self,
+        target_path: &str,
+        context: &Context,
+    ) -> Result<StreamManifest, BackupError> {
+        self.commander.read_manifest(target_path, context)
+    }
+
+    fn delete_incomplete_streams(
+        &mut self,
+        target_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        self.commander
+            .remove_partial_stream_files(target_path, context)
+    }
+
+    fn confirm_stream(
+        &mut self,
+        target_path: &str,
+        snapshot: &SubvolumeInfo,
+        context: &Context,
+    ) -> Result<bool, BackupError> {
+        // the snapshot was not sent in dry-run mode
+        if self.dry_run {
+            log::info!(
+                "dry run: assuming snapshot \"{}\" was stored",
+                snapshot.fs_path
+            );
+            return Ok(true);
+        }
+
+        Ok(self
+            .get_stream_manifest(target_path, context)?
+            .get(&snapshot.uuid)
+            .is_some())
+    }
+
+    fn find_stream_common_parent(
+        &mut self,
+        parent_subvolume_path: &str,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        target_path: &str,
+        context_target: &Context,
+    ) -> Result<Option<Subvolume>, BackupError> {
+        let parent_subvolume = self
+            .commander
+            .get_subvolume_info(parent_subvolume_path, context_local)?;
+        let subvolumes_local = self
+            .commander
+            .get_subvolumes(local_subvolume_path, context_local)?
+            .into_iter()
+            .filter(|sv| sv.parent_uuid == Some(parent_subvolume.uuid))
+            .collect();
+        let subvolumes_target = self
+            .get_stream_manifest(target_path, context_target)?
+            .subvolumes();
+
+        Ok(ActionsSystem::<C>::get_common_parent(&subvolumes_local, &subvolumes_target)?.cloned())
+    }
+
+    fn get_stream_snapshots(
+        &mut self,
+        target_path: &str,
+        suffix: &str,
+        context: &Context,
+    ) -> Result<Vec<(DateTime<Utc>, Subvolume)>, BackupError> {
+        let mut snapshots: Vec<(DateTime<Utc>, Subvolume)> = self
+            .get_stream_manifest(target_path, context)?
+            .subvolumes()
+            .into_iter()
+            .filter_map(|sv| {
+                ActionsSystem::<C>::parse_snapshot_timestamp(&sv.btrfs_path, suffix)
+                    .map(|date| (date, sv))
+            })
+            .collect();
+
+        snapshots.sort_by_key(|(date, _)| *date);
+
+        Ok(snapshots)
+    }
+
+    fn plan_stream_retention(
+        &mut self,
+        target_path: &str,
+        context: &Context,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        suffix: &str,
+        override_guards: bool,
+    ) -> Result<Vec<RetentionDecision>, BackupError> {
+        let manifest = self.get_stream_manifest(target_path, context)?;
+        let snapshots = self.get_stream_snapshots(target_path, suffix, context)?;
+        let mut decisions = ActionsSystem::<C>::decide_retention(
+            snapshots,
+            latest_local_snapshot,
+            common_parents,
+            policy,
+            timestamp,
+            override_guards,
+        )?;
+        // an incremental stream can only be restored together with the streams it depends on
+        let required: HashSet<Uuid> = decisions
+            .iter()
+            .filter(|d| d.keep)
+            .filter_map(|d| manifest.chain(&d.subvolume.uuid))
+            .flatten()
+            .map(|entry| entry.uuid)
+            .collect();
+
+        for decision in decisions
+            .iter_mut()
+            .filter(|d| !d.keep && required.contains(&d.subvolume.uuid))
+        {
+            decision.keep = true;
+            decision.reason = String::from("required by a retained incremental stream");
+        }
+
+        Ok(decisions)
+    }
+
+    fn police_streams(
+        &mut self,
+        target_path: &str,
+        context_local: &Context,
+        context: &Context,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        suffix: &str,
+        override_guards: bool,
+    ) -> Result<Vec<String>, BackupError> {
+        let decisions = self.plan_stream_retention(
+            target_path,
+            context,
+            latest_local_snapshot,
+            common_parents,
+            policy,
+            timestamp,
+            suffix,
+            override_guards,
+        )?;
+
+        ActionsSystem::<C>::check_deletion_limit(target_path, &decisions, policy, override_guards)?;
+
+        let deleted: HashSet<Uuid> = decisions
+            .iter()
+            .filter(|d| !d.keep)
+            .map(|d| d.subvolume.uuid)
+            .collect();
+
+        if deleted.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut manifest = self.get_stream_manifest(target_path, context)?;
+        let (removed, retained): (Vec<StreamEntry>, Vec<StreamEntry>) = manifest
+            .streams
+            .into_iter()
+            .partition(|entry| deleted.contains(&entry.uuid));
+
+        manifest.streams = retained;
+        self.commander
+            .write_manifest(target_path, &manifest, context_local, context)?;
+
+        let mut deleted_files = Vec::new();
+
+        for (entry, decision) in removed.iter().filter_map(|entry| {
+            decisions
+                .iter()
+                .find(|d| d.subvolume.uuid == entry.uuid)
+                .map(|d| (entry, d))
+        }) {
+            let file_path = format!("{}/{}", target_path.trim_end_matches('/'), entry.file);
+
+            log::info!(
+                file = file_path.as_str(), uuid:% = entry.uuid;
+                "deleting stream file: \"{}\" ({})",
+                file_path,
+                decision.reason
+            );
+            self.commander.remove_stream_file(&file_path, context)?;
+            deleted_files.push(file_path);
+        }
+
+        Ok(deleted_files)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::MockCommander;
+    use crate::retention::RetentionCounts;
+    use chrono::TimeZone;
+
+    fn manifest() -> StreamManifest {
+        StreamManifest {
+            streams: [(1, None), (2, Some(1)), (3, None), (4, Some(3))]
+                .iter()
+                .map(|&(uuid, parent_uuid)| StreamEntry {
+                    snapshot: format!("/snapshots/2020-05-0{}T12:00:00Z_test", uuid),
+                    uuid: Uuid::from_u128(uuid),
+                    parent_uuid: parent_uuid.map(Uuid::from_u128),
+                    file: format!("2020-05-0{}T12:00:00Z_test.btrfs", uuid),
+                    compression: None,
+                    encryption: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn police_streams() {
+        let mut mock = MockCommander::new();
+        let context = Context::Local {
+            user: "test".into(),
+        };
+        let latest = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-04T12:00:00Z_test".into(),
+            fs_path: "/data/snapshots/2020-05-04T12:00:00Z_test".into(),
+            uuid: Uuid::from_u128(4),
+        };
+
+        mock.expect_read_manifest().returning(|_, _| Ok(manifest()));
+        mock.expect_write_manifest()
+            .once()
+            .withf(|_, manifest, _, _| {
+                manifest
+                    .streams
+                    .iter()
+                    .map(|e| e.uuid)
+                    .collect::<Vec<Uuid>>()
+                    == vec![Uuid::from_u128(3), Uuid::from_u128(4)]
+            })
+            .returning(|_, _, _, _| Ok(()));
+        mock.expect_remove_stream_file()
+            .times(2)
+            .withf(|path, _| {
+                path == "/nas/2020-05-01T12:00:00Z_test.btrfs"
+                    || path == "/nas/2020-05-02T12:00:00Z_test.btrfs"
+            })
+            .returning(|_, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+        let policy = RetentionPolicy::Counts(RetentionCounts {
+            keep_last: 1,
+            ..RetentionCounts::default()
+        });
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap().into();
+
+        let decisions = actions
+            .plan_stream_retention(
+                "/nas",
+                &context,
+                &latest,
+                &[],
+                &policy,
+                &timestamp,
+                "test",
+                false,
+            )
+            .unwrap();
+
+        // the full stream of the latest snapshot is required to restore it
+        assert_eq!(
+            decisions.iter().map(|d| d.keep).collect::<Vec<bool>>(),
+            vec![false, false, true, true]
+        );
+        assert_eq!(
+            decisions[2].reason,
+            "required by a retained incremental stream"
+        );
+
+        assert_eq!(
+            actions
+                .police_streams(
+                    "/nas",
+                    &context,
+                    &context,
+                    &latest,
+                    &[],
+                    &policy,
+                    &timestamp,
+                    "test",
+                    false,
+                )
+                .unwrap(),
+            vec![
+                "/nas/2020-05-01T12:00:00Z_test.btrfs",
+                "/nas/2020-05-02T12:00:00Z_test.btrfs"
+            ]
+        );
+    }
+}
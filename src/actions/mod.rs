@@ -1,16 +1,22 @@
 use crate::backup_error::BackupError;
 use crate::commands::{Commander, Commands};
-use crate::custom_duration::CustomDuration;
 use crate::objects::*;
-use chrono::{DateTime, Duration, FixedOffset, Utc};
+use crate::retention::RetentionPolicy;
+use chrono::{DateTime, FixedOffset, Utc};
 use exec_rs::{CommandExec, Context};
-use policer::police;
 use std::collections::HashMap;
-use std::convert::TryInto;
 use std::path::Path;
 use uuid::Uuid;
 
-pub trait Actions {
+mod file_target;
+mod notify;
+mod restore;
+
+pub use file_target::ActionsFileTarget;
+pub use notify::ActionsNotify;
+pub use restore::ActionsRestore;
+
+pub trait Actions: ActionsFileTarget + ActionsNotify + ActionsRestore {
     /// Create snapshot
     ///
     /// * `source_subvolume_path` - path of the subvolume that serves as the parent of the new snapshot
@@ -42,6 +48,11 @@ pub trait Actions {
     /// * `remote_subvolume_path` - path of the subvolume to receive the snapshot
     /// * `remote_snapshot_path` - path of the remote snapshots
     /// * `context_remote` - context for executing remote commands
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `catch_up` - also send the local snapshots between the common parent and the snapshot in chronological order, each incrementally from its predecessor
+    /// * `options` - transfer options (the required programs are checked on both hosts before the transfer starts)
+    ///
+    /// Returns the path and the transfer statistics of every snapshot sent, for which statistics were reported.
     fn send_snapshot(
         &mut self,
         parent_subvolume_path: &str,
@@ -53,47 +64,193 @@ pub trait Actions {
         remote_subvolume_path: &str,
         remote_snapshot_path: &str,
         context_remote: &Context,
-    ) -> Result<(), BackupError>;
+        suffix: &str,
+        catch_up: bool,
+        options: &TransferOptions,
+    ) -> Result<Vec<(String, TransferStats)>, BackupError>;
     /// Police snapshots
     ///
+    /// Returns the filesystem paths of the deleted snapshots.
+    ///
     /// * `subvolume_path` - path of the subvolume containing the snapshots to be policed
     /// * `context` - context in which to execute the commands
     /// * `latest_local_snapshot` - latest local snapshot (will be excluded from the deletion list, if contained)
+    /// * `common_parents` - uuids of the newest snapshots shared between the local and the remote host (will be excluded from the deletion list; matched against the uuid and the received uuid)
     /// * `policy` - policy to be applied
     /// * `timestamp` - timestamp to use as the current moment
     /// * `suffix` - suffix of the snapshots (used for filtering)
     /// * `device` - path of the device and links to it as applicable
     /// * `mount_information` - mount information (used to translate btrfs paths in to filesystem paths)
+    /// * `override_guards` - ignore the `min_keep` and `max_delete_per_run` guards of the policy
     fn police_snapshots(
         &mut self,
         subvolume_path: &str,
         context: &Context,
         latest_local_snapshot: &SubvolumeInfo,
-        policy: &Vec<CustomDuration>,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
         timestamp: &DateTime<FixedOffset>,
         suffix: &str,
         device: &[String],
         mount_information: &Vec<MountInformation>,
-    ) -> Result<(), BackupError>;
+        override_guards: bool,
+    ) -> Result<Vec<String>, BackupError>;
+    /// Plan retention
+    ///
+    /// Applies the policy to the snapshots without deleting any of them.
+    /// Returns a decision for every snapshot ordered from oldest to newest.
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the snapshots to be policed
+    /// * `context` - context in which to execute the commands
+    /// * `latest_local_snapshot` - latest local snapshot (will always be kept, if contained)
+    /// * `common_parents` - uuids of the newest snapshots shared between the local and the remote host (will always be kept; matched against the uuid and the received uuid)
+    /// * `policy` - policy to be applied
+    /// * `timestamp` - timestamp to use as the current moment
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `override_guards` - ignore the `min_keep` guard of the policy
+    fn plan_retention(
+        &mut self,
+        subvolume_path: &str,
+        context: &Context,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        suffix: &str,
+        override_guards: bool,
+    ) -> Result<Vec<RetentionDecision>, BackupError>;
+    /// Delete incomplete snapshots
+    ///
+    /// Snapshots without a received uuid, which are still writable, were not received completely (e.g. due to an interrupted transfer) and must not serve as parents.
+    /// Read-only snapshots are never deleted, as they were created locally rather than received.
+    /// Returns the filesystem paths of the deleted snapshots.
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the received snapshots
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `device` - path of the device and links to it as applicable
+    /// * `mount_information` - mount information (used to translate btrfs paths in to filesystem paths)
+    /// * `context` - context in which to execute the commands
+    fn delete_incomplete_snapshots(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        device: &[String],
+        mount_information: &Vec<MountInformation>,
+        context: &Context,
+    ) -> Result<Vec<String>, BackupError>;
+    /// Confirm snapshot
+    ///
+    /// Checks whether a snapshot was received completely.
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the received snapshots
+    /// * `snapshot` - snapshot that was sent
+    /// * `context` - context in which to execute the commands
+    fn confirm_snapshot(
+        &mut self,
+        subvolume_path: &str,
+        snapshot: &SubvolumeInfo,
+        context: &Context,
+    ) -> Result<bool, BackupError>;
+    /// Find common parent
+    ///
+    /// Get the newest local snapshot of the parent subvolume, which was received by the remote host.
+    ///
+    /// * `parent_subvolume_path` - path of the parent subvolume of the snapshots
+    /// * `local_subvolume_path` - path the subvolume containing the local snapshots
+    /// * `context_local` - context for executing local commands
+    /// * `remote_subvolume_path` - path of the subvolume containing the remote snapshots
+    /// * `context_remote` - context for executing remote commands
+    fn find_common_parent(
+        &mut self,
+        parent_subvolume_path: &str,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        remote_subvolume_path: &str,
+        context_remote: &Context,
+    ) -> Result<Option<Subvolume>, BackupError>;
     /// Read link
     ///
     /// * `path` - path of the link to be read
     /// * `context` - the context to use for the execution of the required commands
     fn read_link(&mut self, path: &str, context: &Context) -> Result<Vec<String>, BackupError>;
+    /// Get snapshots
+    ///
+    /// Returns the snapshots with the given suffix together with the timestamp parsed from their name, ordered from oldest to newest.
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the snapshots
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `context` - context in which to execute the commands
+    fn get_snapshots(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        context: &Context,
+    ) -> Result<Vec<(DateTime<Utc>, Subvolume)>, BackupError>;
+    /// Get latest snapshot
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the snapshots
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `device` - path of the device and links to it as applicable
+    /// * `mount_information` - mount information (used to translate btrfs paths in to filesystem paths)
+    /// * `context` - context in which to execute the commands
+    fn get_latest_snapshot(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        device: &[String],
+        mount_information: &Vec<MountInformation>,
+        context: &Context,
+    ) -> Result<Option<SubvolumeInfo>, BackupError>;
+    /// Acquire lock
+    ///
+    /// Creates a lock directory; a lock left behind by a process, which is no longer running on this host, is removed first.
+    ///
+    /// * `lock_path` - path of the lock directory
+    /// * `owner` - owner of the lock (i.e. the current process)
+    /// * `context` - context in which to execute the commands
+    fn acquire_lock(
+        &mut self,
+        lock_path: &str,
+        owner: &LockOwner,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+    /// Release lock
+    ///
+    /// * `lock_path` - path of the lock directory
+    /// * `owner` - owner of the lock (i.e. the current process)
+    /// * `context` - context in which to execute the commands
+    fn release_lock(
+        &mut self,
+        lock_path: &str,
+        owner: &LockOwner,
+        context: &Context,
+    ) -> Result<(), BackupError>;
 }
 
+/// # ActionsSystem
+///
+/// * `commander` - commander used to execute the commands
+/// * `dry_run` - if set, the system is not modified; the actions that would be taken are logged instead
 pub struct ActionsSystem<C: Commands> {
     commander: C,
+    dry_run: bool,
 }
 
-impl Default for ActionsSystem<Commander<CommandExec>> {
-    fn default() -> Self {
+impl ActionsSystem<Commander<CommandExec>> {
+    pub fn new(dry_run: bool) -> Self {
         ActionsSystem {
-            commander: Commander::default(),
+            commander: Commander::new(dry_run),
+            dry_run,
         }
     }
 }
 
+impl Default for ActionsSystem<Commander<CommandExec>> {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
 impl<C: Commands> ActionsSystem<C> {
     /// Get the newest subvolume that was used received on the remote host and is still available locally
     ///
@@ -158,6 +315,46 @@ impl<C: Commands> ActionsSystem<C> {
         )
     }
 
+    /// Parse the timestamp from the name of a snapshot
+    ///
+    /// Snapshot names have the format `<timestamp in rfc3339 format>_<suffix>`.
+    ///
+    /// * `btrfs_path` - btrfs path of the snapshot
+    /// * `suffix` - expected suffix of the snapshot
+    pub fn parse_snapshot_timestamp(btrfs_path: &str, suffix: &str) -> Option<DateTime<Utc>> {
+        btrfs_path
+            .rfind('/')
+            .and_then(|idx| btrfs_path[(idx + 1)..].strip_suffix(&("_".to_string() + suffix)))
+            .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+            .map(DateTime::<Utc>::from)
+    }
+
+    /// Find the newest snapshot taken at or before the given time
+    ///
+    /// * `snapshots` - snapshots together with their timestamps ordered from oldest to newest
+    /// * `timestamp` - time before which the snapshot was taken
+    pub fn find_snapshot_before<'a>(
+        snapshots: &'a [(DateTime<Utc>, Subvolume)],
+        timestamp: &DateTime<Utc>,
+    ) -> Option<&'a Subvolume> {
+        snapshots
+            .iter()
+            .rev()
+            .find(|(date, _)| date <= timestamp)
+            .map(|(_, sv)| sv)
+    }
+
+    /// Check whether a subvolume is one of the given common parents or a received copy of one
+    ///
+    /// * `sv` - subvolume to be checked
+    /// * `common_parents` - uuids of the common parents
+    pub fn is_common_parent(sv: &Subvolume, common_parents: &[Uuid]) -> bool {
+        common_parents.contains(&sv.uuid)
+            || sv
+                .received_uuid
+                .is_some_and(|uuid| common_parents.contains(&uuid))
+    }
+
     pub fn eq_or_received(sv: &Subvolume, svi: &SubvolumeInfo) -> bool {
         sv.uuid == svi.uuid
             || sv
@@ -168,6 +365,106 @@ impl<C: Commands> ActionsSystem<C> {
                 })
                 .is_some()
     }
+
+    /// Decide for every snapshot whether it is kept or deleted
+    ///
+    /// * `snapshots` - snapshots together with their timestamps ordered from oldest to newest
+    /// * `latest_local_snapshot` - latest local snapshot (will be kept)
+    /// * `common_parents` - uuids of the newest snapshots shared between the local and the remote host (will be kept)
+    /// * `policy` - policy to be applied
+    /// * `timestamp` - timestamp to use as the current moment
+    /// * `override_guards` - ignore the `min_keep` guard of the policy
+    pub fn decide_retention(
+        snapshots: Vec<(DateTime<Utc>, Subvolume)>,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        override_guards: bool,
+    ) -> Result<Vec<RetentionDecision>, BackupError> {
+        log::debug!("snapshots: {}", snapshots.len());
+
+        // apply the policy
+        let marks = policy.apply(&DateTime::<Utc>::from(*timestamp), &snapshots)?;
+
+        log::debug!(
+            "subvolumes not retained by the policy: {}",
+            marks.iter().filter(|m| !m.keep).count()
+        );
+
+        let mut decisions: Vec<RetentionDecision> = snapshots
+            .into_iter()
+            .zip(marks)
+            .map(|((date, sv), mark)| {
+                let (keep, reason) =
+                    if ActionsSystem::<C>::eq_or_received(&sv, latest_local_snapshot) {
+                        (true, "latest snapshot")
+                    } else if ActionsSystem::<C>::is_common_parent(&sv, common_parents) {
+                        (
+                            true,
+                            "newest common parent of the local and remote snapshots",
+                        )
+                    } else if !mark.keep {
+                        (false, "not retained by the retention policy")
+                    } else {
+                        (true, "retained by the retention policy")
+                    };
+
+                RetentionDecision {
+                    timestamp: date,
+                    subvolume: sv,
+                    bucket: mark.bucket,
+                    keep,
+                    reason: reason.to_string(),
+                }
+            })
+            .collect();
+
+        if let (Some(min_keep), false) = (policy.min_keep(), override_guards) {
+            let mut kept = decisions.iter().filter(|d| d.keep).count();
+
+            // keep the newest of the snapshots to be deleted until the minimum is reached
+            for decision in decisions.iter_mut().rev().filter(|d| !d.keep) {
+                if kept >= min_keep {
+                    break;
+                }
+
+                decision.keep = true;
+                decision.reason = format!("minimum number of snapshots ({})", min_keep);
+                kept += 1;
+            }
+        }
+
+        Ok(decisions)
+    }
+
+    /// Ensure that the number of deletions does not exceed the limit of the policy
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the snapshots (used in the error)
+    /// * `decisions` - decisions of the policy
+    /// * `policy` - policy applied
+    /// * `override_guards` - ignore the `max_delete_per_run` guard of the policy
+    pub fn check_deletion_limit(
+        subvolume_path: &str,
+        decisions: &[RetentionDecision],
+        policy: &RetentionPolicy,
+        override_guards: bool,
+    ) -> Result<(), BackupError> {
+        let count = decisions.iter().filter(|d| !d.keep).count();
+
+        // an unexpectedly large number of deletions hints at a clock jump or a misconfigured policy
+        if let (Some(limit), false) = (policy.max_delete_per_run(), override_guards) {
+            if count > limit {
+                return Err(BackupError::DeletionLimitExceeded {
+                    subvolume_path: subvolume_path.to_string(),
+                    count,
+                    limit,
+                });
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<C: Commands> Actions for ActionsSystem<C> {
@@ -186,6 +483,16 @@ impl<C: Commands> Actions for ActionsSystem<C> {
             &Utc::now(),
             context,
         )?;
+
+        // the snapshot does not exist in dry-run mode
+        if self.dry_run {
+            return Ok(SubvolumeInfo {
+                btrfs_path: snapshot_path.clone(),
+                fs_path: snapshot_path,
+                uuid: Uuid::nil(),
+            });
+        }
+
         // get snapshot/subvolume information
         self.commander.get_subvolume_info(&snapshot_path, context)
     }
@@ -201,60 +508,125 @@ impl<C: Commands> Actions for ActionsSystem<C> {
         remote_subvolume_path: &str,
         remote_snapshot_path: &str,
         context_remote: &Context,
-    ) -> Result<(), BackupError> {
-        // get parent subvolume info
-        let parent_subvolume = self
-            .commander
-            .get_subvolume_info(parent_subvolume_path, context_local)?;
-
-        // get local snapshots, which are children of the supplied parent subvolume
-        let subvolumes_local = self
-            .commander
-            .get_subvolumes(local_subvolume_path, &context_local)?
-            .iter()
-            .filter(|sv| match sv.parent_uuid {
-                Some(sv_uuid) => sv_uuid == parent_subvolume.uuid,
-                None => false,
-            })
-            .cloned()
-            .collect();
+        suffix: &str,
+        catch_up: bool,
+        options: &TransferOptions,
+    ) -> Result<Vec<(String, TransferStats)>, BackupError> {
+        // compression is only applied to remote targets
+        if let (Some(compression), Context::Remote { host: _, config: _ }) =
+            (&options.compression, context_remote)
+        {
+            self.commander
+                .check_program(compression.program(), context_local)?;
+            self.commander
+                .check_program(compression.program(), context_remote)?;
+        }
 
-        // get remote snapshots
-        let subvolumes_remote = self
-            .commander
-            .get_subvolumes(remote_subvolume_path, &context_remote)?;
+        if options.buffer_args().is_some() {
+            self.commander
+                .check_program(TransferOptions::BUFFER_PROGRAM, context_local)?;
+        }
 
         // find common parent
-        let common_parent =
-            ActionsSystem::<C>::get_common_parent(&subvolumes_local, &subvolumes_remote)?
-                .map(|sv| {
-                    Ok::<SubvolumeInfo, BackupError>(SubvolumeInfo {
-                        btrfs_path: sv.btrfs_path.clone(),
-                        fs_path: ActionsSystem::<C>::btrfs_to_fs_path(
-                            local_mount_information,
-                            local_device,
-                            &sv.btrfs_path,
-                        )?,
-                        uuid: sv.uuid,
-                    })
+        let common_parent = self.find_common_parent(
+            parent_subvolume_path,
+            local_subvolume_path,
+            context_local,
+            remote_subvolume_path,
+            context_remote,
+        )?;
+        let mut parent = common_parent
+            .as_ref()
+            .map(|sv| {
+                Ok::<SubvolumeInfo, BackupError>(SubvolumeInfo {
+                    fs_path: ActionsSystem::<C>::btrfs_to_fs_path(
+                        local_mount_information,
+                        local_device,
+                        &sv.btrfs_path,
+                    )?,
+                    btrfs_path: sv.btrfs_path.clone(),
+                    uuid: sv.uuid,
                 })
-                .transpose()?;
-
-        match &common_parent {
-            Some(s) => log::info!("found common parent snapshot \"{}\"", &s.fs_path),
-            None => log::info!("no common parent snapshot found"),
+            })
+            .transpose()?;
+        let mut snapshots = Vec::new();
+
+        // the snapshots created after the common parent have not been received by the remote host yet
+        if let (true, Some(common_parent)) = (catch_up, &common_parent) {
+            if let Some(parent_timestamp) =
+                ActionsSystem::<C>::parse_snapshot_timestamp(&common_parent.btrfs_path, suffix)
+            {
+                let snapshot_timestamp =
+                    ActionsSystem::<C>::parse_snapshot_timestamp(&snapshot.btrfs_path, suffix);
+
+                for (timestamp, sv) in
+                    self.get_snapshots(local_subvolume_path, suffix, context_local)?
+                {
+                    if sv.parent_uuid == common_parent.parent_uuid
+                        && sv.uuid != snapshot.uuid
+                        && timestamp > parent_timestamp
+                        && !snapshot_timestamp.is_some_and(|ts| timestamp >= ts)
+                    {
+                        snapshots.push(SubvolumeInfo {
+                            fs_path: ActionsSystem::<C>::btrfs_to_fs_path(
+                                local_mount_information,
+                                local_device,
+                                &sv.btrfs_path,
+                            )?,
+                            btrfs_path: sv.btrfs_path,
+                            uuid: sv.uuid,
+                        });
+                    }
+                }
+
+                if !snapshots.is_empty() {
+                    log::info!(
+                        "catching up on {} snapshots missing on the remote host",
+                        snapshots.len()
+                    );
+                }
+            }
         }
 
-        // send remote backup
-        self.commander.send_snapshot(
-            snapshot,
-            common_parent.as_ref(),
-            &context_local,
-            remote_snapshot_path,
-            &context_remote,
-        )?;
+        snapshots.push(snapshot.clone());
+
+        let mut transfers = Vec::new();
+
+        for snapshot in snapshots {
+            match &parent {
+                Some(s) => log::info!(
+                    snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid, parent = s.fs_path.as_str();
+                    "sending snapshot \"{}\" incrementally; found common parent snapshot \"{}\"",
+                    snapshot.fs_path,
+                    &s.fs_path
+                ),
+                None => log::info!(
+                    snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid;
+                    "sending snapshot \"{}\" completely; no common parent snapshot found",
+                    snapshot.fs_path
+                ),
+            }
 
-        Ok(())
+            // send remote backup
+            if let Some(stats) = self.commander.send_snapshot(
+                &snapshot,
+                parent.as_ref(),
+                context_local,
+                remote_snapshot_path,
+                context_remote,
+                options,
+            )? {
+                log::info!(
+                    snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid, bytes = stats.bytes, duration = stats.seconds;
+                    "sent snapshot \"{}\": {}", snapshot.fs_path, stats
+                );
+                transfers.push((snapshot.fs_path.clone(), stats));
+            }
+
+            parent = Some(snapshot);
+        }
+
+        Ok(transfers)
     }
 
     fn police_snapshots(
@@ -262,52 +634,170 @@ impl<C: Commands> Actions for ActionsSystem<C> {
         subvolume_path: &str,
         context: &Context,
         latest_local_snapshot: &SubvolumeInfo,
-        policy: &Vec<CustomDuration>,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
         timestamp: &DateTime<FixedOffset>,
         suffix: &str,
         device: &[String],
         mount_information: &Vec<MountInformation>,
-    ) -> Result<(), BackupError> {
-        // get subvolumes
-        let subvolumes = self.commander.get_subvolumes(subvolume_path, context)?;
-        // filter out the relevant snapshots
-        let snapshots: Vec<(DateTime<Utc>, Subvolume)> = subvolumes
-            .iter()
-            // .filter(|sv| sv.btrfs_path.ends_with(suffix))
-            .filter_map(|sv| {
-                sv.btrfs_path
-                    .rfind('/')
-                    .and_then(|idx| {
-                        sv.btrfs_path[(idx + 1)..].strip_suffix(&("_".to_string() + suffix))
-                    })
-                    .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
-                    .and_then(|date| Some((DateTime::<Utc>::from(date), sv.clone())))
-            })
-            .collect();
+        override_guards: bool,
+    ) -> Result<Vec<String>, BackupError> {
+        let decisions = self.plan_retention(
+            subvolume_path,
+            context,
+            latest_local_snapshot,
+            common_parents,
+            policy,
+            timestamp,
+            suffix,
+            override_guards,
+        )?;
 
-        log::debug!("snapshots: {}", snapshots.len());
+        ActionsSystem::<C>::check_deletion_limit(
+            subvolume_path,
+            &decisions,
+            policy,
+            override_guards,
+        )?;
 
-        // convert the policy
-        let policy: Vec<Duration> = policy.iter().map(|d| d.try_into()).flatten().collect();
-        // apply the policy
-        let to_be_deleted = police(&DateTime::<Utc>::from(*timestamp), &policy, &snapshots);
+        let mut deleted = Vec::new();
+
+        for decision in decisions.iter().filter(|d| !d.keep) {
+            let subvolume_path = ActionsSystem::<C>::btrfs_to_fs_path(
+                mount_information,
+                device,
+                &decision.subvolume.btrfs_path,
+            )?;
+            log::info!(
+                snapshot = subvolume_path.as_str(), uuid:% = decision.subvolume.uuid;
+                "deleting subvolume: \"{}\" ({})",
+                subvolume_path,
+                decision.reason
+            );
+            self.commander.delete_subvolume(&subvolume_path, context)?;
+            deleted.push(subvolume_path);
+        }
 
-        log::debug!("subvolumes to be deleted: {}", to_be_deleted.len());
+        Ok(deleted)
+    }
 
-        // filter out the latest local snapshot
-        for sv in to_be_deleted.iter().filter_map(|sv| {
-            match ActionsSystem::<C>::eq_or_received(&sv.1, latest_local_snapshot) {
-                true => None,
-                false => Some(sv.1.clone()),
-            }
-        }) {
+    fn plan_retention(
+        &mut self,
+        subvolume_path: &str,
+        context: &Context,
+        latest_local_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        policy: &RetentionPolicy,
+        timestamp: &DateTime<FixedOffset>,
+        suffix: &str,
+        override_guards: bool,
+    ) -> Result<Vec<RetentionDecision>, BackupError> {
+        // get the relevant snapshots
+        let snapshots = self.get_snapshots(subvolume_path, suffix, context)?;
+
+        ActionsSystem::<C>::decide_retention(
+            snapshots,
+            latest_local_snapshot,
+            common_parents,
+            policy,
+            timestamp,
+            override_guards,
+        )
+    }
+
+    fn delete_incomplete_snapshots(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        device: &[String],
+        mount_information: &Vec<MountInformation>,
+        context: &Context,
+    ) -> Result<Vec<String>, BackupError> {
+        let mut deleted = Vec::new();
+
+        for (_, sv) in self
+            .get_snapshots(subvolume_path, suffix, context)?
+            .iter()
+            .filter(|(_, sv)| sv.received_uuid.is_none())
+        {
             let subvolume_path =
                 ActionsSystem::<C>::btrfs_to_fs_path(mount_information, device, &sv.btrfs_path)?;
-            log::info!("deleting subvolume: \"{}\"", subvolume_path);
+
+            // btrfs receive only makes a subvolume read-only once it is complete
+            if self.commander.is_read_only(&subvolume_path, context)? {
+                log::warn!(
+                    snapshot = subvolume_path.as_str(), uuid:% = sv.uuid;
+                    "not deleting read-only subvolume without received uuid: \"{}\"",
+                    subvolume_path
+                );
+                continue;
+            }
+
+            log::warn!(
+                snapshot = subvolume_path.as_str(), uuid:% = sv.uuid;
+                "deleting subvolume: \"{}\" (incomplete transfer)",
+                subvolume_path
+            );
             self.commander.delete_subvolume(&subvolume_path, context)?;
+            deleted.push(subvolume_path);
         }
 
-        Ok(())
+        Ok(deleted)
+    }
+
+    fn confirm_snapshot(
+        &mut self,
+        subvolume_path: &str,
+        snapshot: &SubvolumeInfo,
+        context: &Context,
+    ) -> Result<bool, BackupError> {
+        // the snapshot was not sent in dry-run mode
+        if self.dry_run {
+            log::info!(
+                "dry run: assuming snapshot \"{}\" was received",
+                snapshot.fs_path
+            );
+            return Ok(true);
+        }
+
+        Ok(self
+            .commander
+            .get_subvolumes(subvolume_path, context)?
+            .iter()
+            .any(|sv| sv.received_uuid == Some(snapshot.uuid)))
+    }
+
+    fn find_common_parent(
+        &mut self,
+        parent_subvolume_path: &str,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        remote_subvolume_path: &str,
+        context_remote: &Context,
+    ) -> Result<Option<Subvolume>, BackupError> {
+        // get parent subvolume info
+        let parent_subvolume = self
+            .commander
+            .get_subvolume_info(parent_subvolume_path, context_local)?;
+
+        // get local snapshots, which are children of the supplied parent subvolume
+        let subvolumes_local = self
+            .commander
+            .get_subvolumes(local_subvolume_path, context_local)?
+            .iter()
+            .filter(|sv| match sv.parent_uuid {
+                Some(sv_uuid) => sv_uuid == parent_subvolume.uuid,
+                None => false,
+            })
+            .cloned()
+            .collect();
+
+        // get remote snapshots
+        let subvolumes_remote = self
+            .commander
+            .get_subvolumes(remote_subvolume_path, context_remote)?;
+
+        Ok(ActionsSystem::<C>::get_common_parent(&subvolumes_local, &subvolumes_remote)?.cloned())
     }
 
     fn get_mount_information(
@@ -320,14 +810,118 @@ impl<C: Commands> Actions for ActionsSystem<C> {
     fn read_link(&mut self, path: &str, context: &Context) -> Result<Vec<String>, BackupError> {
         self.commander.read_link(path, context)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::commands::MockCommander;
-    use chrono::TimeZone;
-    use mockall::Sequence;
+    fn get_snapshots(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        context: &Context,
+    ) -> Result<Vec<(DateTime<Utc>, Subvolume)>, BackupError> {
+        let mut snapshots: Vec<(DateTime<Utc>, Subvolume)> = self
+            .commander
+            .get_subvolumes(subvolume_path, context)?
+            .into_iter()
+            .filter_map(|sv| {
+                ActionsSystem::<C>::parse_snapshot_timestamp(&sv.btrfs_path, suffix)
+                    .map(|date| (date, sv))
+            })
+            .collect();
+
+        snapshots.sort_by_key(|(date, _)| *date);
+
+        Ok(snapshots)
+    }
+
+    fn get_latest_snapshot(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        device: &[String],
+        mount_information: &Vec<MountInformation>,
+        context: &Context,
+    ) -> Result<Option<SubvolumeInfo>, BackupError> {
+        self.get_snapshots(subvolume_path, suffix, context)?
+            .pop()
+            .map(|(_, sv)| {
+                Ok(SubvolumeInfo {
+                    fs_path: ActionsSystem::<C>::btrfs_to_fs_path(
+                        mount_information,
+                        device,
+                        &sv.btrfs_path,
+                    )?,
+                    btrfs_path: sv.btrfs_path,
+                    uuid: sv.uuid,
+                })
+            })
+            .transpose()
+    }
+
+    fn acquire_lock(
+        &mut self,
+        lock_path: &str,
+        owner: &LockOwner,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let error =
+            match self
+                .commander
+                .create_lock_directory(lock_path, &owner.to_string(), context)
+            {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+        // the lock directory could not be created; report the original error, if it does not exist
+        let holders = match self.commander.get_lock_owners(lock_path, context) {
+            Ok(holders) => holders,
+            Err(_) => return Err(error),
+        };
+        let stale = !holders.is_empty()
+            && holders.iter().all(|holder| {
+                holder
+                    .parse::<LockOwner>()
+                    .is_ok_and(|holder| holder.is_stale(owner))
+            });
+
+        if !stale {
+            return Err(BackupError::Locked {
+                lock: lock_path.to_string(),
+                owner: match holders.is_empty() {
+                    true => String::from("an unknown process"),
+                    false => holders.join(", "),
+                },
+            });
+        }
+
+        log::warn!(
+            "removing stale lock \"{}\" held by {}",
+            lock_path,
+            holders.join(", ")
+        );
+        self.commander
+            .remove_lock_directory(lock_path, &holders, context)?;
+        self.commander
+            .create_lock_directory(lock_path, &owner.to_string(), context)
+    }
+
+    fn release_lock(
+        &mut self,
+        lock_path: &str,
+        owner: &LockOwner,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        self.commander
+            .remove_lock_directory(lock_path, &[owner.to_string()], context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::MockCommander;
+    use crate::custom_duration::CustomDuration;
+    use crate::retention::RetentionCounts;
+    use chrono::TimeZone;
+    use mockall::Sequence;
 
     #[test]
     fn eq_uuid_uuid() {
@@ -395,6 +989,120 @@ mod test {
         ));
     }
 
+    #[test]
+    fn parse_snapshot_timestamp() {
+        assert_eq!(
+            ActionsSystem::<Commander<CommandExec>>::parse_snapshot_timestamp(
+                "/snapshots/2021-05-02T07:40:32Z_inf_btrfs_test",
+                "inf_btrfs_test"
+            ),
+            Some(Utc.with_ymd_and_hms(2021, 5, 2, 7, 40, 32).unwrap())
+        );
+        assert_eq!(
+            ActionsSystem::<Commander<CommandExec>>::parse_snapshot_timestamp(
+                "/snapshots/2021-05-02T07:40:32Z_other",
+                "inf_btrfs_test"
+            ),
+            None
+        );
+        assert_eq!(
+            ActionsSystem::<Commander<CommandExec>>::parse_snapshot_timestamp(
+                "/opt/btrfs_test",
+                "btrfs_test"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn find_snapshot_before() {
+        let snapshots: Vec<(DateTime<Utc>, Subvolume)> = [1, 3, 5]
+            .iter()
+            .map(|&day| {
+                (
+                    Utc.with_ymd_and_hms(2021, 5, day, 7, 0, 0).unwrap(),
+                    Subvolume {
+                        btrfs_path: format!("/snapshots/2021-05-0{}T07:00:00Z_test", day),
+                        uuid: Uuid::from_u128(day as u128),
+                        parent_uuid: None,
+                        received_uuid: None,
+                    },
+                )
+            })
+            .collect();
+        let find = |day, hour| {
+            ActionsSystem::<Commander<CommandExec>>::find_snapshot_before(
+                &snapshots,
+                &Utc.with_ymd_and_hms(2021, 5, day, hour, 0, 0).unwrap(),
+            )
+            .map(|sv| sv.uuid)
+        };
+
+        assert_eq!(find(4, 12), Some(Uuid::from_u128(3)));
+        assert_eq!(find(5, 7), Some(Uuid::from_u128(5)));
+        assert_eq!(find(1, 6), None);
+    }
+
+    #[test]
+    fn get_latest_snapshot() {
+        let mut mock = MockCommander::new();
+        let context = Context::Local {
+            user: "test_user".into(),
+        };
+        let mount_information = vec![MountInformation {
+            device: String::from("/dev/some/device"),
+            fs_type: String::from("btrfs"),
+            mount_point: String::from("/data"),
+            root: String::from("/"),
+            properties: HashMap::new(),
+        }];
+
+        mock.expect_get_subvolumes().once().returning(|_, _| {
+            Ok(vec![
+                Subvolume {
+                    parent_uuid: None,
+                    btrfs_path: "/subvolume".into(),
+                    received_uuid: None,
+                    uuid: Uuid::parse_str("5f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                },
+                Subvolume {
+                    parent_uuid: None,
+                    btrfs_path: "/snapshots/2020-05-10T12:00:00Z_test".into(),
+                    received_uuid: None,
+                    uuid: Uuid::parse_str("4f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                },
+                Subvolume {
+                    parent_uuid: None,
+                    btrfs_path: "/snapshots/2020-05-09T12:00:00Z_test".into(),
+                    received_uuid: None,
+                    uuid: Uuid::parse_str("3f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                },
+            ])
+        });
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert_eq!(
+            actions
+                .get_latest_snapshot(
+                    "/",
+                    "test",
+                    &["/dev/some/device".into()],
+                    &mount_information,
+                    &context
+                )
+                .unwrap(),
+            Some(SubvolumeInfo {
+                btrfs_path: "/snapshots/2020-05-10T12:00:00Z_test".into(),
+                fs_path: "/data/snapshots/2020-05-10T12:00:00Z_test".into(),
+                uuid: Uuid::parse_str("4f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+            })
+        );
+    }
+
     #[test]
     fn btrfs_to_fs_path_1() {
         let mi = vec![
@@ -560,7 +1268,10 @@ mod test {
                 })
             });
 
-        let mut actions = ActionsSystem { commander: mock };
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
 
         let test_path = actions
             .create_snapshot(subvolume_path, snapshot_path, snapshot_suffix, &context)
@@ -569,6 +1280,38 @@ mod test {
         assert_eq!(test_path, subvolume_info);
     }
 
+    #[test]
+    fn create_snapshot_dry_run() {
+        let mut mock = MockCommander::new();
+        let context = Context::Local {
+            user: "test_user".into(),
+        };
+        let new_snapshot_path = "/snapshot/path/2022-12-11T21:24:04+01:00_snapshot_suffix";
+
+        mock.expect_snapshot_subvolume()
+            .times(1)
+            .returning(move |_, _, _, _, _| Ok(new_snapshot_path.into()));
+        mock.expect_get_subvolume_info().never();
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: true,
+        };
+
+        assert_eq!(
+            actions
+                .create_snapshot(
+                    "/subvolume/path",
+                    "/snapshot/path",
+                    "snapshot_suffix",
+                    &context
+                )
+                .unwrap()
+                .fs_path,
+            new_snapshot_path
+        );
+    }
+
     #[test]
     fn send_snapshot_parent() {
         let mut mock = MockCommander::new();
@@ -665,7 +1408,8 @@ mod test {
                       f_common_parent,
                       f_context_local,
                       f_backup_path,
-                      f_context_remote| {
+                      f_context_remote,
+                      _| {
                     assert!(f_common_parent.is_some());
                     assert_eq!(f_backup_path, backup_snapshot_path);
                     assert_eq!(f_subvolume_info.fs_path, "/backup/path");
@@ -678,11 +1422,14 @@ mod test {
                         _ => false,
                     });
 
-                    Ok(())
+                    Ok(None)
                 },
             );
 
-        let mut actions = ActionsSystem { commander: mock };
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
 
         actions
             .send_snapshot(
@@ -695,6 +1442,9 @@ mod test {
                 backup_subvolume_path,
                 backup_snapshot_path,
                 &context_remote,
+                "test",
+                false,
+                &TransferOptions::default(),
             )
             .unwrap();
     }
@@ -794,7 +1544,8 @@ mod test {
                       f_common_parent,
                       f_context_local,
                       f_backup_path,
-                      f_context_remote| {
+                      f_context_remote,
+                      _| {
                     assert_eq!(f_subvolume_info.fs_path, "/backup/path");
                     assert!(f_common_parent.is_none());
                     assert_eq!(f_backup_path, backup_snapshot_path);
@@ -806,11 +1557,14 @@ mod test {
                         Context::Remote { host: _, config: _ } => true,
                         _ => false,
                     });
-                    Ok(())
+                    Ok(None)
                 },
             );
 
-        let mut actions = ActionsSystem { commander: mock };
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
 
         actions
             .send_snapshot(
@@ -823,10 +1577,236 @@ mod test {
                 backup_subvolume_path,
                 backup_snapshot_path,
                 &context_remote,
+                "test",
+                false,
+                &TransferOptions::default(),
             )
             .unwrap();
     }
 
+    #[test]
+    fn plan_retention_latest_snapshot() {
+        let mut mock = MockCommander::new();
+        let context = Context::Local {
+            user: "test_user".into(),
+        };
+        let snapshot_uuid = Uuid::parse_str("4f0b151b-52e4-4445-aa94-d07056733a1f").unwrap();
+        let latest_local_snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-10T11:30:00Z_test".into(),
+            uuid: snapshot_uuid,
+            fs_path: String::from("/data/snapshots/2020-05-10T11:30:00Z_test"),
+        };
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
+
+        mock.expect_get_subvolumes().once().returning(move |_, _| {
+            Ok(vec![Subvolume {
+                parent_uuid: None,
+                btrfs_path: "/snapshots/2020-05-10T11:30:00Z_test".into(),
+                received_uuid: None,
+                uuid: snapshot_uuid,
+            }])
+        });
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        let decisions = actions
+            .plan_retention(
+                "/",
+                &context,
+                &latest_local_snapshot,
+                &[],
+                &RetentionPolicy::Durations(vec![
+                    CustomDuration::days(1),
+                    CustomDuration::hours(1),
+                ]),
+                &timestamp.into(),
+                "test",
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(
+            decisions[0].timestamp,
+            Utc.with_ymd_and_hms(2020, 5, 10, 11, 30, 0).unwrap()
+        );
+        assert_eq!(decisions[0].bucket, Some(String::from("1 hour")));
+        assert!(decisions[0].keep);
+    }
+
+    #[test]
+    fn plan_retention_common_parent() {
+        let mut mock = MockCommander::new();
+        let context = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let common_parent = Uuid::parse_str("4f0b151b-52e4-4445-aa94-d07056733a1f").unwrap();
+        let latest_local_snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-10T11:30:00Z_test".into(),
+            uuid: Uuid::parse_str("5f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+            fs_path: String::from("/data/snapshots/2020-05-10T11:30:00Z_test"),
+        };
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
+
+        mock.expect_get_subvolumes().once().returning(move |_, _| {
+            Ok(vec![Subvolume {
+                parent_uuid: None,
+                btrfs_path: "/backups/2020-04-01T11:30:00Z_test".into(),
+                received_uuid: Some(common_parent),
+                uuid: Uuid::parse_str("6f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+            }])
+        });
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        let decisions = actions
+            .plan_retention(
+                "/",
+                &context,
+                &latest_local_snapshot,
+                &[common_parent],
+                &RetentionPolicy::Durations(vec![CustomDuration::hours(1)]),
+                &timestamp.into(),
+                "test",
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(decisions.len(), 1);
+        assert_eq!(decisions[0].bucket, None);
+        assert!(decisions[0].keep);
+    }
+
+    #[test]
+    fn delete_incomplete_snapshots() {
+        let mut mock = MockCommander::new();
+        let context = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let mount_information = vec![MountInformation {
+            device: String::from("/dev/some/device"),
+            fs_type: String::from("btrfs"),
+            mount_point: String::from("/data"),
+            root: String::from("/"),
+            properties: HashMap::new(),
+        }];
+        let mut seq = Sequence::new();
+
+        mock.expect_get_subvolumes()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(vec![
+                    Subvolume {
+                        parent_uuid: None,
+                        btrfs_path: "/backups/2020-05-09T12:00:00Z_test".into(),
+                        received_uuid: Some(
+                            Uuid::parse_str("3f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                        ),
+                        uuid: Uuid::parse_str("4f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                    },
+                    Subvolume {
+                        parent_uuid: None,
+                        btrfs_path: "/backups/2020-05-10T12:00:00Z_test".into(),
+                        received_uuid: None,
+                        uuid: Uuid::parse_str("5f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                    },
+                    // created locally
+                    Subvolume {
+                        parent_uuid: None,
+                        btrfs_path: "/backups/2020-05-11T12:00:00Z_test".into(),
+                        received_uuid: None,
+                        uuid: Uuid::parse_str("6f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                    },
+                ])
+            });
+        mock.expect_is_read_only()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|path, _| path == "/data/backups/2020-05-10T12:00:00Z_test")
+            .returning(|_, _| Ok(false));
+        mock.expect_delete_subvolume()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|f_subvolume_path, _| {
+                assert_eq!(f_subvolume_path, "/data/backups/2020-05-10T12:00:00Z_test");
+                Ok(())
+            });
+        mock.expect_is_read_only()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|path, _| path == "/data/backups/2020-05-11T12:00:00Z_test")
+            .returning(|_, _| Ok(true));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert_eq!(
+            actions
+                .delete_incomplete_snapshots(
+                    "/",
+                    "test",
+                    &["/dev/some/device".into()],
+                    &mount_information,
+                    &context
+                )
+                .unwrap(),
+            vec![String::from("/data/backups/2020-05-10T12:00:00Z_test")]
+        );
+    }
+
+    #[test]
+    fn confirm_snapshot() {
+        let mut mock = MockCommander::new();
+        let context = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-10T12:00:00Z_test".into(),
+            fs_path: "/data/snapshots/2020-05-10T12:00:00Z_test".into(),
+            uuid: Uuid::parse_str("3f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+        };
+
+        mock.expect_get_subvolumes().times(2).returning(|_, _| {
+            Ok(vec![Subvolume {
+                parent_uuid: None,
+                btrfs_path: "/backups/2020-05-10T12:00:00Z_test".into(),
+                received_uuid: Some(
+                    Uuid::parse_str("3f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+                ),
+                uuid: Uuid::parse_str("4f0b151b-52e4-4445-aa94-d07056733a1f").unwrap(),
+            }])
+        });
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(actions.confirm_snapshot("/", &snapshot, &context).unwrap());
+        assert!(!actions
+            .confirm_snapshot(
+                "/",
+                &SubvolumeInfo {
+                    uuid: Uuid::nil(),
+                    ..snapshot.clone()
+                },
+                &context
+            )
+            .unwrap());
+    }
+
     #[test]
     fn police_local_snapshots() {
         let mut mock = MockCommander::new();
@@ -841,7 +1821,7 @@ mod test {
             uuid: Uuid::nil(),
             fs_path: String::from("/some/path"),
         };
-        let policy = vec![CustomDuration::minutes(10)];
+        let policy = RetentionPolicy::Durations(vec![CustomDuration::minutes(10)]);
         let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
         let mut seq = Sequence::new();
         let subvolume_path = "/";
@@ -875,19 +1855,447 @@ mod test {
                 ])
             });
 
-        let mut actions = ActionsSystem { commander: mock };
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
 
         actions
             .police_snapshots(
                 subvolume_path,
                 &context,
                 &latest_local_snapshot,
+                &[],
                 &policy,
                 &timestamp.into(),
                 suffix,
                 &["/dev/some/device".into()],
                 &mount_information,
+                false,
+            )
+            .unwrap();
+    }
+
+    fn guard_snapshots() -> Vec<Subvolume> {
+        (1..=4)
+            .map(|day| Subvolume {
+                parent_uuid: None,
+                btrfs_path: format!("/snapshots/2020-05-0{}T12:00:00Z_test", day),
+                received_uuid: None,
+                uuid: Uuid::from_u128(day),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plan_retention_min_keep() {
+        let mut mock = MockCommander::new();
+        let context = Context::Local {
+            user: "test_user".into(),
+        };
+        let latest_local_snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-04T12:00:00Z_test".into(),
+            uuid: Uuid::from_u128(4),
+            fs_path: String::from("/data/snapshots/2020-05-04T12:00:00Z_test"),
+        };
+        let policy = RetentionPolicy::Counts(RetentionCounts {
+            keep_last: 1,
+            min_keep: Some(3),
+            ..RetentionCounts::default()
+        });
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
+
+        mock.expect_get_subvolumes()
+            .times(2)
+            .returning(|_, _| Ok(guard_snapshots()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        let decisions = actions
+            .plan_retention(
+                "/",
+                &context,
+                &latest_local_snapshot,
+                &[],
+                &policy,
+                &timestamp.into(),
+                "test",
+                false,
             )
             .unwrap();
+
+        assert_eq!(
+            decisions.iter().map(|d| d.keep).collect::<Vec<bool>>(),
+            vec![false, true, true, true]
+        );
+        assert_eq!(decisions[1].reason, "minimum number of snapshots (3)");
+
+        let decisions = actions
+            .plan_retention(
+                "/",
+                &context,
+                &latest_local_snapshot,
+                &[],
+                &policy,
+                &timestamp.into(),
+                "test",
+                true,
+            )
+            .unwrap();
+
+        assert_eq!(
+            decisions.iter().map(|d| d.keep).collect::<Vec<bool>>(),
+            vec![false, false, false, true]
+        );
+    }
+
+    #[test]
+    fn police_snapshots_deletion_limit() {
+        let mut mock = MockCommander::new();
+        let context = Context::Local {
+            user: "test_user".into(),
+        };
+        let latest_local_snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-04T12:00:00Z_test".into(),
+            uuid: Uuid::from_u128(4),
+            fs_path: String::from("/data/snapshots/2020-05-04T12:00:00Z_test"),
+        };
+        let policy = RetentionPolicy::Counts(RetentionCounts {
+            keep_last: 1,
+            max_delete_per_run: Some(2),
+            ..RetentionCounts::default()
+        });
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
+        let mount_information = vec![MountInformation {
+            device: String::from("/dev/some/device"),
+            fs_type: String::from("btrfs"),
+            mount_point: String::from("/data"),
+            root: String::from("/"),
+            properties: HashMap::new(),
+        }];
+
+        mock.expect_get_subvolumes()
+            .times(2)
+            .returning(|_, _| Ok(guard_snapshots()));
+        mock.expect_delete_subvolume()
+            .times(3)
+            .returning(|_, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(matches!(
+            actions.police_snapshots(
+                "/",
+                &context,
+                &latest_local_snapshot,
+                &[],
+                &policy,
+                &timestamp.into(),
+                "test",
+                &["/dev/some/device".into()],
+                &mount_information,
+                false,
+            ),
+            Err(BackupError::DeletionLimitExceeded {
+                count: 3,
+                limit: 2,
+                ..
+            })
+        ));
+
+        actions
+            .police_snapshots(
+                "/",
+                &context,
+                &latest_local_snapshot,
+                &[],
+                &policy,
+                &timestamp.into(),
+                "test",
+                &["/dev/some/device".into()],
+                &mount_information,
+                true,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn acquire_lock_held() {
+        let mut mock = MockCommander::new();
+        let context = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+
+        mock.expect_create_lock_directory()
+            .once()
+            .returning(|_, _, _| {
+                Err(BackupError::Command {
+                    command: String::from("sudo mkdir /backups/.lock"),
+                    context: String::from("remote (host)"),
+                    exit_code: Some(1),
+                    stderr: Some(String::from("mkdir: cannot create directory: File exists")),
+                    source: None,
+                })
+            });
+        mock.expect_get_lock_owners()
+            .once()
+            .returning(|_, _| Ok(vec![String::from("1234@other")]));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(matches!(
+            actions.acquire_lock(
+                "/backups/.lock",
+                &LockOwner {
+                    pid: 1,
+                    host: "host".into()
+                },
+                &context
+            ),
+            Err(BackupError::Locked { owner, .. }) if owner == "1234@other"
+        ));
+    }
+
+    #[test]
+    fn acquire_lock_stale() {
+        let mut mock = MockCommander::new();
+        let context = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let mut seq = Sequence::new();
+
+        mock.expect_create_lock_directory()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _, _| {
+                Err(BackupError::Command {
+                    command: String::from("sudo mkdir /backups/.lock"),
+                    context: String::from("remote (host)"),
+                    exit_code: Some(1),
+                    stderr: Some(String::from("mkdir: cannot create directory: File exists")),
+                    source: None,
+                })
+            });
+        // pid 0 never appears in /proc
+        mock.expect_get_lock_owners()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _| Ok(vec![String::from("0@host")]));
+        mock.expect_remove_lock_directory()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|lock_path, owners, _| {
+                lock_path == "/backups/.lock" && owners == [String::from("0@host")]
+            })
+            .returning(|_, _, _| Ok(()));
+        mock.expect_create_lock_directory()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|_, owner, _| owner == "1@host")
+            .returning(|_, _, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        actions
+            .acquire_lock(
+                "/backups/.lock",
+                &LockOwner {
+                    pid: 1,
+                    host: "host".into(),
+                },
+                &context,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn send_snapshot_catch_up() {
+        let mut mock = MockCommander::new();
+        let context_local = Context::Local {
+            user: "test_user".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let source_uuid = Uuid::from_u128(1);
+        let snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-10T12:00:00Z_test".into(),
+            fs_path: "/data/snapshots/2020-05-10T12:00:00Z_test".into(),
+            uuid: Uuid::from_u128(4),
+        };
+        let local_subvolumes = move || {
+            let mut subvolumes = vec![Subvolume {
+                parent_uuid: None,
+                btrfs_path: "/home".into(),
+                received_uuid: None,
+                uuid: source_uuid,
+            }];
+
+            subvolumes.extend((2..=4).map(|idx| Subvolume {
+                parent_uuid: Some(source_uuid),
+                btrfs_path: format!("/snapshots/2020-05-{:02}T12:00:00Z_test", idx + 6),
+                received_uuid: None,
+                uuid: Uuid::from_u128(idx as u128),
+            }));
+
+            subvolumes
+        };
+        let mount_information = vec![MountInformation {
+            device: String::from("/dev/some/device"),
+            fs_type: String::from("btrfs"),
+            mount_point: String::from("/data"),
+            root: String::from("/"),
+            properties: HashMap::new(),
+        }];
+        let mut seq = Sequence::new();
+
+        mock.expect_get_subvolume_info()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(move |_, _| {
+                Ok(SubvolumeInfo {
+                    btrfs_path: "/home".into(),
+                    fs_path: "/home".into(),
+                    uuid: source_uuid,
+                })
+            });
+        mock.expect_get_subvolumes()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(move |_, _| Ok(local_subvolumes()));
+        mock.expect_get_subvolumes()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(|_, _| {
+                Ok(vec![Subvolume {
+                    parent_uuid: None,
+                    btrfs_path: "/backups/2020-05-08T12:00:00Z_test".into(),
+                    received_uuid: Some(Uuid::from_u128(2)),
+                    uuid: Uuid::from_u128(12),
+                }])
+            });
+        mock.expect_get_subvolumes()
+            .once()
+            .in_sequence(&mut seq)
+            .returning(move |_, _| Ok(local_subvolumes()));
+        mock.expect_send_snapshot()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|snapshot, parent, _, _, _, _| {
+                snapshot.uuid == Uuid::from_u128(3)
+                    && parent.is_some_and(|p| p.uuid == Uuid::from_u128(2))
+            })
+            .returning(|_, _, _, _, _, _| Ok(None));
+        mock.expect_send_snapshot()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|snapshot, parent, _, _, _, _| {
+                snapshot.uuid == Uuid::from_u128(4)
+                    && parent.is_some_and(|p| p.uuid == Uuid::from_u128(3))
+            })
+            .returning(|_, _, _, _, _, _| Ok(None));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        actions
+            .send_snapshot(
+                "/home",
+                &["/dev/some/device".into()],
+                "/",
+                &mount_information,
+                &snapshot,
+                &context_local,
+                "/",
+                "/backups",
+                &context_remote,
+                "test",
+                true,
+                &TransferOptions::default(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn send_snapshot_compression_unavailable() {
+        let mut mock = MockCommander::new();
+        let context_local = Context::Local {
+            user: "test_user".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot = SubvolumeInfo {
+            btrfs_path: "/snapshots/2020-05-10T12:00:00Z_test".into(),
+            fs_path: "/data/snapshots/2020-05-10T12:00:00Z_test".into(),
+            uuid: Uuid::from_u128(4),
+        };
+        let mut seq = Sequence::new();
+
+        mock.expect_check_program()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|program, context| program == "zstd" && matches!(context, Context::Local { .. }))
+            .returning(|_, _| Ok(()));
+        mock.expect_check_program()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|program, context| {
+                program == "zstd" && matches!(context, Context::Remote { .. })
+            })
+            .returning(|program, _| {
+                Err(BackupError::ProgramNotAvailable {
+                    program: program.into(),
+                    location: "host".into(),
+                })
+            });
+        mock.expect_send_snapshot().never();
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(matches!(
+            actions.send_snapshot(
+                "/home",
+                &["/dev/some/device".into()],
+                "/",
+                &Vec::new(),
+                &snapshot,
+                &context_local,
+                "/",
+                "/backups",
+                &context_remote,
+                "test",
+                false,
+                &TransferOptions {
+                    compression: Some(Compression {
+                        algorithm: CompressionAlgorithm::Zstd,
+                        level: None,
+                    }),
+                    ..TransferOptions::default()
+                },
+            ),
+            Err(BackupError::ProgramNotAvailable { .. })
+        ));
     }
 }
@@ -0,0 +1,157 @@
+use super::ActionsSystem;
+use crate::backup_error::BackupError;
+use crate::commands::Commands;
+use crate::objects::*;
+use exec_rs::Context;
+
+/// Actions for notifying about the outcome of job runs
+pub trait ActionsNotify {
+    /// Deliver a notification with a notifier
+    ///
+    /// * `notifier` - notifier delivering the notification
+    /// * `notification` - notification to be delivered
+    /// * `context` - context for executing the commands of the notifier
+    fn notify(
+        &mut self,
+        notifier: &Notifier,
+        notification: &Notification,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+}
+
+impl<C: Commands> ActionsNotify for ActionsSystem<C> {
+    fn notify(
+        &mut self,
+        notifier: &Notifier,
+        notification: &Notification,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        match notifier {
+            Notifier::Sendmail {
+                command, from, to, ..
+            } => {
+                let mut message = vec![format!("To: {}", to.join(", "))];
+
+                if let Some(from) = from {
+                    message.push(format!("From: {}", from));
+                }
+
+                message.push(format!("Subject: {}", notification.summary()));
+                message.push(String::from("Content-Type: text/plain; charset=utf-8"));
+                message.push(String::new());
+                message.push(notification.body());
+
+                self.commander
+                    .send_mail(command, &message.join("\n"), context)
+            }
+            Notifier::Webhook { url, .. } => {
+                let payload = serde_json::to_string(notification)?;
+
+                self.commander.post_json(url, &payload, context)
+            }
+            Notifier::NotifySend { user, .. } => self.commander.notify_desktop(
+                user.as_deref(),
+                match notification.event {
+                    NotificationEvent::Failure => "critical",
+                    _ => "normal",
+                },
+                &notification.summary(),
+                &notification.body(),
+                context,
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::MockCommander;
+    use chrono::{TimeZone, Utc};
+
+    fn notification() -> Notification {
+        Notification {
+            job: String::from("home"),
+            event: NotificationEvent::Failure,
+            timestamp: Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap(),
+            host: String::from("laptop"),
+            snapshot: None,
+            errors: vec![String::from("failed targets: nas")],
+        }
+    }
+
+    #[test]
+    fn notify_sendmail() {
+        let mut mock = MockCommander::new();
+
+        mock.expect_send_mail()
+            .once()
+            .withf(|command, message, _| {
+                assert_eq!(command, "sendmail");
+                assert_eq!(
+                    message,
+                    "To: admin@example.com, ops@example.com\nFrom: backup@laptop\nSubject: backup-btrfs on laptop: job \"home\" failed\nContent-Type: text/plain; charset=utf-8\n\nrun started at 2020-05-10T12:00:00+00:00\nerrors:\n  failed targets: nas"
+                );
+                true
+            })
+            .returning(|_, _, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(actions
+            .notify(
+                &Notifier::Sendmail {
+                    command: String::from("sendmail"),
+                    from: Some(String::from("backup@laptop")),
+                    to: vec![
+                        String::from("admin@example.com"),
+                        String::from("ops@example.com")
+                    ],
+                    events: vec![NotificationEvent::Failure],
+                },
+                &notification(),
+                &Context::Local {
+                    user: "test".into()
+                },
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn notify_webhook() {
+        let mut mock = MockCommander::new();
+
+        mock.expect_post_json()
+            .once()
+            .withf(|url, payload, _| {
+                assert_eq!(url, "https://example.com/hook");
+                assert_eq!(
+                    serde_json::from_str::<serde_json::Value>(payload).unwrap()["errors"][0],
+                    "failed targets: nas"
+                );
+                true
+            })
+            .returning(|_, _, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(actions
+            .notify(
+                &Notifier::Webhook {
+                    url: String::from("https://example.com/hook"),
+                    events: vec![NotificationEvent::Failure],
+                },
+                &notification(),
+                &Context::Local {
+                    user: "test".into()
+                },
+            )
+            .is_ok());
+    }
+}
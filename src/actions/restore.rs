@@ -0,0 +1,535 @@
+use super::{Actions, ActionsFileTarget, ActionsSystem};
+use crate::backup_error::BackupError;
+use crate::commands::Commands;
+use crate::objects::*;
+use chrono::{DateTime, Utc};
+use exec_rs::Context;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Actions for restoring snapshots from the targets
+pub trait ActionsRestore {
+    /// Restore a snapshot from a btrfs target
+    ///
+    /// The snapshot is sent incrementally from the newest older snapshot on the target, which was received from a snapshot still available locally.
+    /// Returns the local path of the restored snapshot.
+    ///
+    /// * `local_subvolume_path` - path of the subvolume containing the local snapshots
+    /// * `context_local` - context for executing local commands
+    /// * `remote_subvolume_path` - path of the subvolume containing the remote snapshots
+    /// * `remote_device` - path of the remote device and links to it
+    /// * `remote_mount_information` - remote mount information
+    /// * `context_remote` - context for executing remote commands
+    /// * `snapshot_name` - name of the snapshot to be restored
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `destination` - local directory to receive the snapshot in (must be on the filesystem of the local snapshots for incremental transfers)
+    fn restore_snapshot(
+        &mut self,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        remote_subvolume_path: &str,
+        remote_device: &[String],
+        remote_mount_information: &Vec<MountInformation>,
+        context_remote: &Context,
+        snapshot_name: &str,
+        suffix: &str,
+        destination: &str,
+    ) -> Result<String, BackupError>;
+    /// Restore a snapshot from a file target
+    ///
+    /// The streams of the chain of the snapshot are received one after another, starting after the newest stream whose snapshot is still available locally.
+    /// The intermediate snapshots received on the way are deleted.
+    /// Returns the local path of the restored snapshot.
+    ///
+    /// * `local_subvolume_path` - path of the subvolume containing the local snapshots
+    /// * `context_local` - context for executing local commands
+    /// * `target_path` - directory of the file target
+    /// * `context_target` - context of the target
+    /// * `snapshot_name` - name of the snapshot to be restored
+    /// * `destination` - local directory to receive the snapshot in (must be on the filesystem of the local snapshots for incremental streams)
+    /// * `identity` - identity file for decrypting the streams (age only)
+    fn restore_stream(
+        &mut self,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        target_path: &str,
+        context_target: &Context,
+        snapshot_name: &str,
+        destination: &str,
+        identity: Option<&str>,
+    ) -> Result<String, BackupError>;
+    /// Copy a file or directory out of the newest snapshot taken at or before the given time to the local host
+    ///
+    /// Ownership, permissions, ACLs, and xattrs are preserved.
+    /// Returns the path of the copied file or directory in the snapshot.
+    ///
+    /// * `subvolume_path` - path of the subvolume containing the snapshots
+    /// * `suffix` - suffix of the snapshots (used for filtering)
+    /// * `device` - path of the device of the snapshots and links to it
+    /// * `mount_information` - mount information of the host of the snapshots
+    /// * `context_snapshot` - context of the host of the snapshots
+    /// * `path` - path of the file or directory relative to the snapshot
+    /// * `before` - time before which the snapshot was taken
+    /// * `destination` - local path to copy to
+    /// * `context_local` - context for executing local commands
+    fn restore_path(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        device: &[String],
+        mount_information: &Vec<MountInformation>,
+        context_snapshot: &Context,
+        path: &str,
+        before: &DateTime<Utc>,
+        destination: &str,
+        context_local: &Context,
+    ) -> Result<String, BackupError>;
+    /// Create a writable snapshot of a restored snapshot, which can replace the source subvolume
+    ///
+    /// * `snapshot_path` - path of the restored snapshot
+    /// * `writable_path` - path of the writable snapshot
+    /// * `context` - context for executing the commands
+    fn make_writable(
+        &mut self,
+        snapshot_path: &str,
+        writable_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+}
+
+/// Get the name of a snapshot from its path
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl<C: Commands> ActionsRestore for ActionsSystem<C> {
+    fn restore_snapshot(
+        &mut self,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        remote_subvolume_path: &str,
+        remote_device: &[String],
+        remote_mount_information: &Vec<MountInformation>,
+        context_remote: &Context,
+        snapshot_name: &str,
+        suffix: &str,
+        destination: &str,
+    ) -> Result<String, BackupError> {
+        let snapshots_remote: Vec<Subvolume> = self
+            .get_snapshots(remote_subvolume_path, suffix, context_remote)?
+            .into_iter()
+            .map(|(_, sv)| sv)
+            .collect();
+        let position = snapshots_remote
+            .iter()
+            .position(|sv| base_name(&sv.btrfs_path) == snapshot_name)
+            .ok_or_else(|| BackupError::SnapshotNotFound {
+                snapshot: snapshot_name.to_string(),
+                location: remote_subvolume_path.to_string(),
+            })?;
+        let uuids_local: HashSet<Uuid> = self
+            .commander
+            .get_subvolumes(local_subvolume_path, context_local)?
+            .iter()
+            .map(|sv| sv.uuid)
+            .collect();
+
+        // the receiving side finds the parent by the uuid, which the parent was received from
+        let common_parent = snapshots_remote[..position].iter().rev().find(|sv| {
+            sv.received_uuid
+                .is_some_and(|uuid| uuids_local.contains(&uuid))
+        });
+        let to_info = |sv: &Subvolume| {
+            Ok::<SubvolumeInfo, BackupError>(SubvolumeInfo {
+                fs_path: ActionsSystem::<C>::btrfs_to_fs_path(
+                    remote_mount_information,
+                    remote_device,
+                    &sv.btrfs_path,
+                )?,
+                btrfs_path: sv.btrfs_path.clone(),
+                uuid: sv.uuid,
+            })
+        };
+        let snapshot = to_info(&snapshots_remote[position])?;
+        let destination = destination.trim_end_matches('/');
+        let parent = common_parent.map(to_info).transpose()?;
+
+        match &parent {
+            Some(p) => log::info!(
+                "restoring snapshot \"{}\" incrementally; found common parent snapshot \"{}\"",
+                snapshot.fs_path,
+                p.fs_path
+            ),
+            None => log::info!(
+                "restoring snapshot \"{}\" completely; no common parent snapshot found",
+                snapshot.fs_path
+            ),
+        }
+
+        // the transfer options of the target only apply to sending snapshots to it
+        if let Some(stats) = self.commander.send_snapshot(
+            &snapshot,
+            parent.as_ref(),
+            context_remote,
+            destination,
+            context_local,
+            &TransferOptions::default(),
+        )? {
+            log::info!(
+                snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid, bytes = stats.bytes, duration = stats.seconds;
+                "restored snapshot \"{}\": {}", snapshot.fs_path, stats
+            );
+        }
+
+        Ok(format!("{}/{}", destination, snapshot_name))
+    }
+
+    fn restore_stream(
+        &mut self,
+        local_subvolume_path: &str,
+        context_local: &Context,
+        target_path: &str,
+        context_target: &Context,
+        snapshot_name: &str,
+        destination: &str,
+        identity: Option<&str>,
+    ) -> Result<String, BackupError> {
+        let manifest = self.get_stream_manifest(target_path, context_target)?;
+        let entry = manifest
+            .streams
+            .iter()
+            .find(|e| base_name(&e.snapshot) == snapshot_name)
+            .ok_or_else(|| BackupError::SnapshotNotFound {
+                snapshot: snapshot_name.to_string(),
+                location: target_path.to_string(),
+            })?;
+        let chain = manifest
+            .chain(&entry.uuid)
+            .ok_or_else(|| BackupError::IncompleteStreamChain(snapshot_name.to_string()))?;
+        let uuids_local: HashSet<Uuid> = self
+            .commander
+            .get_subvolumes(local_subvolume_path, context_local)?
+            .iter()
+            .flat_map(|sv| [Some(sv.uuid), sv.received_uuid])
+            .flatten()
+            .collect();
+
+        // the streams up to the newest snapshot available locally are not required
+        let start = chain[..chain.len() - 1]
+            .iter()
+            .rposition(|e| uuids_local.contains(&e.uuid))
+            .map_or(0, |position| position + 1);
+        let destination = destination.trim_end_matches('/');
+        let mut intermediate: Option<String> = None;
+
+        log::info!(
+            "restoring snapshot \"{}\" from {} streams",
+            snapshot_name,
+            chain.len() - start
+        );
+
+        for e in &chain[start..] {
+            log::info!("receiving stream file \"{}\"", e.file);
+
+            self.commander.receive_stream_file(
+                &format!("{}/{}", target_path.trim_end_matches('/'), e.file),
+                context_target,
+                e,
+                identity,
+                destination,
+                context_local,
+            )?;
+
+            // the intermediate snapshot is no longer required as the parent of the next stream
+            if let Some(path) = intermediate.take() {
+                match self.dry_run {
+                    true => log::info!(
+                        "dry run: skipping deletion of intermediate snapshot \"{}\"",
+                        path
+                    ),
+                    false => self.commander.delete_subvolume(&path, context_local)?,
+                }
+            }
+
+            intermediate = Some(format!("{}/{}", destination, base_name(&e.snapshot)));
+        }
+
+        Ok(format!("{}/{}", destination, snapshot_name))
+    }
+
+    fn restore_path(
+        &mut self,
+        subvolume_path: &str,
+        suffix: &str,
+        device: &[String],
+        mount_information: &Vec<MountInformation>,
+        context_snapshot: &Context,
+        path: &str,
+        before: &DateTime<Utc>,
+        destination: &str,
+        context_local: &Context,
+    ) -> Result<String, BackupError> {
+        let snapshots = self.get_snapshots(subvolume_path, suffix, context_snapshot)?;
+        let snapshot =
+            ActionsSystem::<C>::find_snapshot_before(&snapshots, before).ok_or_else(|| {
+                BackupError::NoSnapshotBefore {
+                    location: subvolume_path.to_string(),
+                    timestamp: before.to_rfc3339(),
+                }
+            })?;
+        let snapshot_path =
+            ActionsSystem::<C>::btrfs_to_fs_path(mount_information, device, &snapshot.btrfs_path)?;
+
+        log::info!("found snapshot \"{}\"", snapshot_path);
+
+        // remote snapshots are copied with rsync, which is required on both hosts
+        if let Context::Remote { host: _, config: _ } = context_snapshot {
+            self.commander.check_program("rsync", context_local)?;
+            self.commander.check_program("rsync", context_snapshot)?;
+        }
+
+        let source = format!(
+            "{}/{}",
+            snapshot_path.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        );
+
+        log::info!("copying \"{}\" to \"{}\"", source, destination);
+        self.commander
+            .copy_path(&source, context_snapshot, destination, context_local)?;
+
+        Ok(source)
+    }
+
+    fn make_writable(
+        &mut self,
+        snapshot_path: &str,
+        writable_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        log::info!(
+            "creating writable snapshot \"{}\" of restored snapshot \"{}\"",
+            writable_path,
+            snapshot_path
+        );
+
+        self.commander
+            .snapshot_subvolume_writable(snapshot_path, writable_path, context)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::MockCommander;
+    use chrono::TimeZone;
+    use std::collections::HashMap;
+
+    fn context() -> Context {
+        Context::Local {
+            user: "test".into(),
+        }
+    }
+
+    fn subvolume(btrfs_path: &str, uuid: u128, received_uuid: Option<u128>) -> Subvolume {
+        Subvolume {
+            btrfs_path: btrfs_path.into(),
+            uuid: Uuid::from_u128(uuid),
+            parent_uuid: None,
+            received_uuid: received_uuid.map(Uuid::from_u128),
+        }
+    }
+
+    #[test]
+    fn restore_snapshot_incremental() {
+        let mut mock = MockCommander::new();
+
+        mock.expect_get_subvolumes()
+            .withf(|path, _| path == "/backups")
+            .returning(|_, _| {
+                Ok(vec![
+                    subvolume("/backups/2020-05-01T12:00:00Z_test", 11, Some(1)),
+                    subvolume("/backups/2020-05-02T12:00:00Z_test", 12, Some(2)),
+                    subvolume("/backups/2020-05-03T12:00:00Z_test", 13, Some(3)),
+                ])
+            });
+        // the second snapshot was already deleted locally
+        mock.expect_get_subvolumes()
+            .withf(|path, _| path == "/snapshots")
+            .returning(|_, _| {
+                Ok(vec![subvolume(
+                    "/snapshots/2020-05-01T12:00:00Z_test",
+                    1,
+                    None,
+                )])
+            });
+        mock.expect_send_snapshot()
+            .once()
+            .withf(|snapshot, parent, _, destination, _, _| {
+                snapshot.fs_path == "/mnt/backups/2020-05-03T12:00:00Z_test"
+                    && parent.unwrap().fs_path == "/mnt/backups/2020-05-01T12:00:00Z_test"
+                    && destination == "/restore"
+            })
+            .returning(|_, _, _, _, _, _| Ok(None));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+        let mount_information = vec![MountInformation {
+            device: "/dev/sdb".into(),
+            mount_point: "/mnt".into(),
+            fs_type: "btrfs".into(),
+            root: "/".into(),
+            properties: HashMap::new(),
+        }];
+
+        assert_eq!(
+            actions
+                .restore_snapshot(
+                    "/snapshots",
+                    &context(),
+                    "/backups",
+                    &["/dev/sdb".into()],
+                    &mount_information,
+                    &context(),
+                    "2020-05-03T12:00:00Z_test",
+                    "test",
+                    "/restore/",
+                )
+                .unwrap(),
+            "/restore/2020-05-03T12:00:00Z_test"
+        );
+    }
+
+    #[test]
+    fn restore_stream() {
+        let mut mock = MockCommander::new();
+
+        mock.expect_read_manifest().returning(|_, _| {
+            Ok(StreamManifest {
+                streams: [(1, None), (2, Some(1)), (3, Some(2))]
+                    .iter()
+                    .map(|&(uuid, parent_uuid)| StreamEntry {
+                        snapshot: format!("/snapshots/2020-05-0{}T12:00:00Z_test", uuid),
+                        uuid: Uuid::from_u128(uuid),
+                        parent_uuid: parent_uuid.map(Uuid::from_u128),
+                        file: format!("2020-05-0{}T12:00:00Z_test.btrfs", uuid),
+                        compression: None,
+                        encryption: None,
+                    })
+                    .collect(),
+            })
+        });
+        mock.expect_get_subvolumes().returning(|_, _| {
+            Ok(vec![subvolume(
+                "/snapshots/2020-05-01T12:00:00Z_test",
+                1,
+                None,
+            )])
+        });
+        mock.expect_receive_stream_file()
+            .times(2)
+            .withf(|file, _, _, _, destination, _| {
+                (file == "/nas/2020-05-02T12:00:00Z_test.btrfs"
+                    || file == "/nas/2020-05-03T12:00:00Z_test.btrfs")
+                    && destination == "/restore"
+            })
+            .returning(|_, _, _, _, _, _| Ok(()));
+        mock.expect_delete_subvolume()
+            .once()
+            .withf(|path, _| path == "/restore/2020-05-02T12:00:00Z_test")
+            .returning(|_, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert_eq!(
+            actions
+                .restore_stream(
+                    "/snapshots",
+                    &context(),
+                    "/nas",
+                    &context(),
+                    "2020-05-03T12:00:00Z_test",
+                    "/restore",
+                    None,
+                )
+                .unwrap(),
+            "/restore/2020-05-03T12:00:00Z_test"
+        );
+    }
+
+    #[test]
+    fn restore_path() {
+        let mut mock = MockCommander::new();
+
+        mock.expect_get_subvolumes().returning(|_, _| {
+            Ok(vec![
+                subvolume("/snapshots/2020-05-01T12:00:00Z_test", 1, None),
+                subvolume("/snapshots/2020-05-03T12:00:00Z_test", 3, None),
+            ])
+        });
+        mock.expect_copy_path()
+            .once()
+            .withf(|source, _, destination, _| {
+                source == "/mnt/snapshots/2020-05-01T12:00:00Z_test/user/doc.txt"
+                    && destination == "/tmp/doc.txt"
+            })
+            .returning(|_, _, _, _| Ok(()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+        let mount_information = vec![MountInformation {
+            device: "/dev/sda".into(),
+            mount_point: "/mnt".into(),
+            fs_type: "btrfs".into(),
+            root: "/".into(),
+            properties: HashMap::new(),
+        }];
+
+        assert!(actions
+            .restore_path(
+                "/snapshots",
+                "test",
+                &["/dev/sda".into()],
+                &mount_information,
+                &context(),
+                "/user/doc.txt",
+                &Utc.with_ymd_and_hms(2020, 5, 2, 12, 0, 0).unwrap(),
+                "/tmp/doc.txt",
+                &context(),
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn restore_unknown_snapshot() {
+        let mut mock = MockCommander::new();
+
+        mock.expect_read_manifest()
+            .returning(|_, _| Ok(StreamManifest::default()));
+
+        let mut actions = ActionsSystem {
+            commander: mock,
+            dry_run: false,
+        };
+
+        assert!(matches!(
+            actions.restore_stream(
+                "/snapshots",
+                &context(),
+                "/nas",
+                &context(),
+                "2020-05-03T12:00:00Z_test",
+                "/restore",
+                None,
+            ),
+            Err(BackupError::SnapshotNotFound { .. })
+        ));
+    }
+}
@@ -1,13 +1,19 @@
+use std::io::ErrorKind;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum BackupError {
-    #[error("command error")]
-    Command,
+    #[error("command \"{command}\" failed on {context}{}", command_details(.exit_code, .stderr, .source))]
+    Command {
+        command: String,
+        context: String,
+        exit_code: Option<i32>,
+        stderr: Option<String>,
+        #[source]
+        source: Option<exec_rs::ExecError>,
+    },
     #[error("error parsing mount information: {0}")]
     MountParsing(String),
-    #[error(transparent)]
-    Exec(#[from] exec_rs::ExecError),
     #[error("error parsing subvolume: {0}")]
     SubvolumeParsing(String),
     #[error(transparent)]
@@ -31,4 +37,179 @@ pub enum BackupError {
     },
     #[error("error creating snapshot: {0}")]
     SnapshotCreation(String),
+    #[error("could not find a snapshot in \"{0}\"")]
+    NoSnapshot(String),
+    #[error("snapshot \"{0}\" was not confirmed on the remote host")]
+    SnapshotNotConfirmed(String),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(
+        "refusing to delete {count} snapshots in \"{subvolume_path}\" (limit per run: {limit})"
+    )]
+    DeletionLimitExceeded {
+        subvolume_path: String,
+        count: usize,
+        limit: usize,
+    },
+    #[error("error parsing lock owner: {0}")]
+    LockOwnerParsing(String),
+    #[error("lock \"{lock}\" is held by {owner}")]
+    Locked { lock: String, owner: String },
+    #[error("failed targets: {0}")]
+    TargetsFailed(String),
+    #[error("program \"{program}\" is not available on {location}")]
+    ProgramNotAvailable { program: String, location: String },
+    #[error("error parsing size of staged send stream: {0}")]
+    StagedStreamSize(String),
+    #[error("snapshot \"{snapshot}\" not found in \"{location}\"")]
+    SnapshotNotFound { snapshot: String, location: String },
+    #[error("the chain of streams of snapshot \"{0}\" is incomplete")]
+    IncompleteStreamChain(String),
+    #[error("no snapshot taken before {timestamp} in \"{location}\"")]
+    NoSnapshotBefore { location: String, timestamp: String },
+    #[error("{0} is not supported for file targets")]
+    UnsupportedForFileTarget(String),
+}
+
+/// Format the exit code and the standard error of a failed command; without the standard error, the error reported by `exec_rs` is appended
+fn command_details(
+    exit_code: &Option<i32>,
+    stderr: &Option<String>,
+    source: &Option<exec_rs::ExecError>,
+) -> String {
+    let mut details = String::new();
+
+    if let Some(code) = exit_code {
+        details.push_str(&format!(" with exit code {}", code));
+    }
+
+    match (stderr, source) {
+        (Some(stderr), _) => details.push_str(&format!(": {}", stderr)),
+        (None, Some(source)) => details.push_str(&format!(": {}", source)),
+        (None, None) => (),
+    }
+
+    details
+}
+
+/// Check whether an I/O error is caused by an interrupted stream or connection
+fn is_interruption(error: &std::io::Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::BrokenPipe
+            | ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionAborted
+            | ErrorKind::NotConnected
+            | ErrorKind::TimedOut
+            | ErrorKind::UnexpectedEof
+            | ErrorKind::Interrupted
+    )
+}
+
+impl BackupError {
+    /// Check whether the error is transient, so that repeating the failed operation can succeed
+    ///
+    /// Transient errors are failed connections of ssh (exit code 255) and interrupted streams or connections (e.g. a broken pipe).
+    pub fn is_transient(&self) -> bool {
+        match self {
+            BackupError::Command {
+                exit_code: Some(255),
+                ..
+            } => true,
+            BackupError::Command {
+                source: Some(source),
+                ..
+            } => {
+                let mut cause: Option<&(dyn std::error::Error + 'static)> = Some(source);
+
+                while let Some(error) = cause {
+                    if error
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(is_interruption)
+                    {
+                        return true;
+                    }
+
+                    cause = error.source();
+                }
+
+                false
+            }
+            BackupError::IoError(e) => is_interruption(e),
+            _ => false,
+        }
+    }
+
+    /// Kind of the error (the name of the variant in snake case), used as a stable field in structured logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            BackupError::Command { .. } => "command",
+            BackupError::MountParsing(..) => "mount_parsing",
+            BackupError::SubvolumeParsing(..) => "subvolume_parsing",
+            BackupError::Uuid(..) => "uuid",
+            BackupError::SubvolumeInfoParsing(..) => "subvolume_info_parsing",
+            BackupError::SnapshotSubvolume(..) => "snapshot_subvolume",
+            BackupError::DeleteSubvolume(..) => "delete_subvolume",
+            BackupError::IoError(..) => "io_error",
+            BackupError::DurationConversionError => "duration_conversion_error",
+            BackupError::PathConversionError { .. } => "path_conversion_error",
+            BackupError::SnapshotCreation(..) => "snapshot_creation",
+            BackupError::NoSnapshot(..) => "no_snapshot",
+            BackupError::SnapshotNotConfirmed(..) => "snapshot_not_confirmed",
+            BackupError::Json(..) => "json",
+            BackupError::DeletionLimitExceeded { .. } => "deletion_limit_exceeded",
+            BackupError::LockOwnerParsing(..) => "lock_owner_parsing",
+            BackupError::Locked { .. } => "locked",
+            BackupError::TargetsFailed(..) => "targets_failed",
+            BackupError::ProgramNotAvailable { .. } => "program_not_available",
+            BackupError::StagedStreamSize(..) => "staged_stream_size",
+            BackupError::SnapshotNotFound { .. } => "snapshot_not_found",
+            BackupError::IncompleteStreamChain(..) => "incomplete_stream_chain",
+            BackupError::NoSnapshotBefore { .. } => "no_snapshot_before",
+            BackupError::UnsupportedForFileTarget(..) => "unsupported_for_file_target",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transient_errors() {
+        let command = |exit_code| BackupError::Command {
+            command: String::from("sudo btrfs receive /backups"),
+            context: String::from("remote (nas)"),
+            exit_code,
+            stderr: None,
+            source: None,
+        };
+
+        assert!(command(Some(255)).is_transient());
+        assert!(!command(Some(1)).is_transient());
+        assert!(!command(None).is_transient());
+        assert!(BackupError::IoError(ErrorKind::BrokenPipe.into()).is_transient());
+        assert!(!BackupError::IoError(ErrorKind::PermissionDenied.into()).is_transient());
+        assert!(!BackupError::ProgramNotAvailable {
+            program: String::from("zstd"),
+            location: String::from("remote (nas)"),
+        }
+        .is_transient());
+    }
+
+    #[test]
+    fn command_error_message() {
+        let error = BackupError::Command {
+            command: String::from("sudo btrfs receive /backups"),
+            context: String::from("remote (nas)"),
+            exit_code: Some(1),
+            stderr: Some(String::from("ERROR: cannot find parent subvolume")),
+            source: None,
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "command \"sudo btrfs receive /backups\" failed on remote (nas) with exit code 1: ERROR: cannot find parent subvolume"
+        );
+    }
 }
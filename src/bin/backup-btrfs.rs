@@ -1,95 +1,780 @@
 extern crate backup_btrfs;
 
-use anyhow::{Context as _, Result as AnyhowResult};
+use anyhow::{anyhow, Context as _, Result as AnyhowResult};
 use backup_btrfs::actions::{Actions, ActionsSystem};
-use backup_btrfs::configuration::Configuration;
-use chrono::Utc;
-use exec_rs::Context;
-use log::{debug, info};
-use std::env;
+use backup_btrfs::backup_error::BackupError;
+use backup_btrfs::configuration::{Configuration, JobConfiguration};
+use backup_btrfs::job_runner::JobRunner;
+use backup_btrfs::lock::LocalLock;
+use backup_btrfs::logging::{self, LogFormat, Scope};
+use backup_btrfs::metrics;
+use backup_btrfs::objects::{
+    BandwidthLimit, InventoryEntry, LockOwner, Notification, NotificationEvent, Outcome,
+    RetentionDecision,
+};
+use backup_btrfs::relay::{relay, throttle};
+use backup_btrfs::state::JobState;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use log::{debug, error, info, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use uuid::Uuid;
+
+/// A backup tool based on btrfs snapshots
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Path of the configuration file
+    #[arg(short, long, global = true, env = "BACKUP_BTRFS_CONFIG")]
+    config: Option<String>,
+    /// Name of a job to process (can be repeated; defaults to all jobs)
+    #[arg(short, long = "job", global = true, value_name = "NAME")]
+    jobs: Vec<String>,
+    /// Only report the commands modifying the system instead of executing them
+    #[arg(long, global = true)]
+    dry_run: bool,
+    /// Ignore the minimum number of snapshots and the deletion limit of the retention policies
+    #[arg(long, global = true)]
+    override_retention_guards: bool,
+    /// Format of the log output (overrides `log_format` of the configuration file; defaults to text)
+    #[arg(long, global = true, value_enum, env = "BACKUP_BTRFS_LOG_FORMAT")]
+    log_format: Option<LogFormat>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Create a snapshot, send it, and apply the retention policies (default)
+    Run,
+    /// Create a new local snapshot
+    Snapshot,
+    /// Send the newest local snapshot to the targets
+    Send,
+    /// Apply the retention policies to the local and target snapshots
+    Prune {
+        /// Only police the snapshots on the given side
+        #[arg(long, value_enum)]
+        only: Option<Side>,
+        /// Show the decision for every snapshot instead of deleting snapshots
+        #[arg(long)]
+        preview: bool,
+        /// Print the preview as JSON (one object per job)
+        #[arg(long, requires = "preview")]
+        json: bool,
+    },
+    /// List the local and target snapshots side by side
+    List {
+        /// Print the snapshots as JSON (one object per job)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the outcome of the last run and the newest local and target snapshots; fails, if the last successful run is too old
+    Status,
+    /// List the snapshots of a target or restore one of them into a local directory
+    Restore {
+        /// Name of the snapshot to restore (lists the snapshots of the target, if omitted)
+        snapshot: Option<String>,
+        /// Name of the target to restore from (required, if the job has several targets)
+        #[arg(long)]
+        target: Option<String>,
+        /// Local directory to receive the snapshot in (must be on the filesystem of the local snapshots for incremental transfers)
+        #[arg(long, requires = "snapshot")]
+        destination: Option<String>,
+        /// Create a writable snapshot of the restored snapshot at this path (e.g. to replace the source subvolume)
+        #[arg(long, requires = "destination")]
+        writable: Option<String>,
+        /// Identity file for decrypting the streams of file targets encrypted with age
+        #[arg(long)]
+        identity: Option<String>,
+    },
+    /// Copy a file or directory out of the newest snapshot taken before a given time
+    RestorePath {
+        /// Path of the file or directory in the source subvolume (either relative to it or absolute)
+        path: String,
+        /// Local path to copy to
+        destination: String,
+        /// Time before which the snapshot was taken (RFC 3339, e.g. "2024-05-07T18:00:00+02:00"; defaults to now)
+        #[arg(long, value_parser = parse_timestamp)]
+        before: Option<DateTime<Utc>>,
+        /// Copy from the snapshots of this target instead of the local snapshots
+        #[arg(long)]
+        target: Option<String>,
+    },
+    /// Check the configuration file
+    CheckConfig,
+    /// Copy stdin to stdout reporting the progress (used in the send pipeline)
+    #[command(hide = true)]
+    Relay {
+        /// Interval between progress reports in seconds
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+        /// File to write the size and duration of the transfer to
+        #[arg(long)]
+        stats_file: String,
+    },
+    /// Copy stdin to stdout limiting the transfer rate (used in the send pipeline)
+    #[command(hide = true)]
+    Throttle {
+        /// Bandwidth limits of the target (JSON)
+        #[arg(long)]
+        bandwidth_limits: String,
+    },
+}
+
+impl Command {
+    /// Check whether the command creates or deletes snapshots and therefore requires the lock of the job
+    fn requires_lock(&self) -> bool {
+        matches!(
+            self,
+            Command::Run
+                | Command::Snapshot
+                | Command::Send
+                | Command::Prune { preview: false, .. }
+                | Command::Restore {
+                    snapshot: Some(_),
+                    ..
+                }
+                | Command::RestorePath { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Side {
+    Local,
+    Remote,
+}
 
 fn main() -> AnyhowResult<()> {
-    env_logger::init();
+    let cli = Cli::parse();
+
+    // the relay is part of the send pipeline and does not require a configuration
+    if let Some(Command::Relay {
+        interval,
+        stats_file,
+    }) = &cli.command
+    {
+        // report the progress of transfers
+        logging::init(cli.log_format.unwrap_or_default(), "info");
+        return run_relay(*interval, stats_file);
+    }
+
+    if let Some(Command::Throttle { bandwidth_limits }) = &cli.command {
+        logging::init(cli.log_format.unwrap_or_default(), "info");
+        return run_throttle(bandwidth_limits);
+    }
 
     // read config file
-    let config_filename = env::var("BACKUP_BTRFS_CONFIG")
-        .context("could not find environment variable BACKUP_BTRFS_CONFIG")?;
+    let config_filename = cli
+        .config
+        .context("no configuration file given (use --config or BACKUP_BTRFS_CONFIG)")?;
     let config = Configuration::read_from_file(&config_filename)?;
 
+    // report the skipped commands in dry-run mode
+    logging::init(
+        cli.log_format.or(config.log_format).unwrap_or_default(),
+        match cli.dry_run {
+            true => "info",
+            false => "error",
+        },
+    );
+
     debug!("configuration read from file \"{}\"", config_filename);
 
-    let mut actions: Box<dyn Actions> = Box::new(ActionsSystem::default());
+    config.validate()?;
 
-    // create local context
-    let context_local = Context::Local {
-        user: config.user_local,
-    };
-    // create remote context
-    let context_remote = Context::Remote {
-        host: config.config_ssh.host,
-        config: config.config_ssh.config,
+    let jobs = config.select_jobs(&cli.jobs)?;
+    let command = cli.command.unwrap_or(Command::Run);
+
+    if let Command::CheckConfig = command {
+        println!(
+            "configuration \"{}\" is valid ({} jobs)",
+            config_filename,
+            config.jobs.len()
+        );
+        return Ok(());
+    }
+
+    if let (
+        Command::Restore {
+            snapshot: Some(_), ..
+        }
+        | Command::RestorePath { .. },
+        false,
+    ) = (&command, jobs.len() == 1)
+    {
+        return Err(anyhow!(
+            "restoring from a snapshot requires selecting a single job (use --job)"
+        ));
+    }
+
+    let mut actions: Box<dyn Actions> = Box::new(ActionsSystem::new(cli.dry_run));
+    let mut failed_jobs = Vec::new();
+
+    for job in &jobs {
+        let _scope = Scope::enter(&[("job", job.name())]);
+
+        info!("starting job \"{}\"", job.name());
+
+        let start = Utc::now();
+        let mut runner = JobRunner::new(&mut *actions, job, cli.override_retention_guards);
+
+        if let (false, Ok(program)) = (cli.dry_run, std::env::current_exe()) {
+            runner.relay_transfers(program.to_string_lossy().to_string());
+        }
+
+        // locks are not required in dry-run mode, as the system is not modified
+        let result = match command.requires_lock() && !cli.dry_run {
+            true => run_command_locked(&command, &mut runner, job, &config.state_directory),
+            false => run_command(&command, &mut runner, job, &config.state_directory),
+        };
+
+        match &result {
+            Ok(()) => info!("job \"{}\" completed", job.name()),
+            Err(e) => {
+                error!(
+                    error_kind = e.downcast_ref::<BackupError>().map_or("other", BackupError::kind);
+                    "job \"{}\" failed: {:?}", job.name(), e
+                );
+                failed_jobs.push(job.name());
+            }
+        }
+
+        if let (Command::Run, false) = (&command, cli.dry_run) {
+            let previous_state = JobState::read(&config.state_directory, job.name());
+
+            if let Err(e) = record_state(
+                &config.state_directory,
+                job,
+                &start,
+                &result,
+                &mut runner,
+                config.metrics_file.is_some(),
+            ) {
+                error!("could not record state of job \"{}\": {:?}", job.name(), e);
+            }
+
+            if !config.notifications.is_empty() {
+                // a failed run is reported as such, even if the previous state is not available
+                let previous_failed = previous_state
+                    .map(|state| state.last_run.is_some() && !state.last_run_successful)
+                    .unwrap_or(false);
+                let notification =
+                    create_notification(job, &start, &result, &runner, previous_failed);
+
+                for notifier in &config.notifications {
+                    if !notifier.is_notified(notification.event) {
+                        continue;
+                    }
+
+                    if let Err(e) = actions.notify(notifier, &notification, &job.context_local()) {
+                        error!(
+                            "could not notify about job \"{}\" with {}: {:?}",
+                            job.name(),
+                            notifier.name(),
+                            e
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    if let (Command::Run, false, Some(metrics_file)) = (&command, cli.dry_run, &config.metrics_file)
+    {
+        if let Err(e) = write_metrics(metrics_file, &config) {
+            error!("could not write metrics to \"{}\": {:?}", metrics_file, e);
+        }
+    }
+
+    if !failed_jobs.is_empty() {
+        return Err(anyhow!(
+            "{} of {} jobs failed: {}",
+            failed_jobs.len(),
+            jobs.len(),
+            failed_jobs.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+fn record_state(
+    state_directory: &str,
+    job: &JobConfiguration,
+    start: &DateTime<Utc>,
+    result: &AnyhowResult<()>,
+    runner: &mut JobRunner,
+    count_snapshots: bool,
+) -> AnyhowResult<()> {
+    let mut state = JobState::read(state_directory, job.name())?;
+
+    state.last_run = Some(*start);
+    state.last_run_end = Some(Utc::now());
+    state.last_run_successful = result.is_ok();
+    state.last_steps = runner.steps().to_vec();
+    state.last_snapshot = runner.created_snapshot().map(String::from);
+    state.last_transfers = runner.transfers().to_vec();
+    state.last_bytes_sent = runner.transfers().iter().map(|t| t.stats.bytes).sum();
+    state.last_deleted_snapshots = runner.deleted_snapshots().to_vec();
+
+    let failed_targets: BTreeSet<&str> = runner
+        .steps()
+        .iter()
+        .filter(|step| step.outcome == Outcome::Failed)
+        .filter_map(|step| step.target.as_deref())
+        .collect();
+
+    for target in failed_targets {
+        *state.target_failures.entry(target.to_string()).or_default() += 1;
+    }
+
+    if result.is_err() {
+        state.failed_runs += 1;
+    }
+
+    // the snapshots are only counted for the metrics, as counting requires listing the snapshots of every target
+    if count_snapshots {
+        state.local_snapshot_count = runner.local_snapshots().ok().map(|s| s.len());
+        state.target_snapshot_counts = BTreeMap::new();
+
+        for target in runner.targets() {
+            match runner.target_snapshots(target) {
+                Ok(snapshots) => {
+                    state
+                        .target_snapshot_counts
+                        .insert(target.name().to_string(), snapshots.len());
+                }
+                Err(e) => warn!(
+                    "could not count snapshots of target \"{}\": {}",
+                    target.name(),
+                    e
+                ),
+            }
+        }
+    }
+
+    match result {
+        Ok(()) => {
+            state.last_success = Some(*start);
+            state.last_error = None;
+            state.last_error_chain = Vec::new();
+        }
+        Err(e) => {
+            state.last_error = Some(format!("{:#}", e));
+            state.last_error_chain = e.chain().map(|cause| cause.to_string()).collect();
+        }
+    }
+
+    state.write(state_directory, job.name())?;
+
+    Ok(())
+}
+
+fn create_notification(
+    job: &JobConfiguration,
+    start: &DateTime<Utc>,
+    result: &AnyhowResult<()>,
+    runner: &JobRunner,
+    previous_failed: bool,
+) -> Notification {
+    let (event, mut errors) = match result {
+        Ok(()) if previous_failed => (NotificationEvent::Recovery, Vec::new()),
+        Ok(()) => (NotificationEvent::Success, Vec::new()),
+        Err(e) => (
+            NotificationEvent::Failure,
+            e.chain().map(|cause| cause.to_string()).collect(),
+        ),
     };
 
-    // create a new local snapshot
-    let new_snapshot_info = actions.create_snapshot(
-        &config.source_subvolume_path,
-        &config.snapshot_path,
-        &config.snapshot_suffix,
-        &context_local,
-    )?;
+    errors.extend(
+        runner
+            .steps()
+            .iter()
+            .filter(|step| step.outcome == Outcome::Failed)
+            .map(|step| {
+                let message = step.message.as_deref().unwrap_or("failed");
 
-    info!("created new snapshot: \"{}\"", new_snapshot_info.fs_path);
-
-    // get device information
-    let snapshot_devices = actions.read_link(&config.snapshot_device, &context_local)?;
-    let backup_devices = actions.read_link(&config.backup_device, &context_remote)?;
-
-    let local_mount_information = actions.get_mount_information(&context_local)?;
-    let remote_mount_information = actions.get_mount_information(&context_remote)?;
-
-    actions.send_snapshot(
-        &config.source_subvolume_path,
-        &snapshot_devices,
-        &config.snapshot_subvolume_path,
-        &local_mount_information,
-        &new_snapshot_info,
-        &context_local,
-        &config.backup_subvolume_path,
-        &config.backup_path,
-        &context_remote,
-    )?;
+                match &step.target {
+                    Some(target) => format!("{} on target {}: {}", step.step, target, message),
+                    None => format!("{}: {}", step.step, message),
+                }
+            }),
+    );
 
-    let timestamp = Utc::now();
+    Notification {
+        job: job.name().to_string(),
+        event,
+        timestamp: *start,
+        host: LockOwner::current()
+            .map(|owner| owner.host)
+            .unwrap_or_else(|_| String::from("unknown")),
+        snapshot: runner.created_snapshot().map(String::from),
+        errors,
+    }
+}
 
-    info!("policing local snapshots");
+fn write_metrics(metrics_file: &str, config: &Configuration) -> AnyhowResult<()> {
+    let states = config
+        .jobs
+        .iter()
+        .map(|job| {
+            Ok((
+                job.name(),
+                JobState::read(&config.state_directory, job.name())?,
+            ))
+        })
+        .collect::<AnyhowResult<Vec<(&str, JobState)>>>()?;
 
-    // police local snapshots
-    actions.police_snapshots(
-        &config.snapshot_subvolume_path,
-        &context_local,
-        &new_snapshot_info,
-        &config.policy_local,
-        &timestamp.into(),
-        &config.snapshot_suffix,
-        &snapshot_devices,
-        &local_mount_information,
+    metrics::write(metrics_file, &metrics::render(&states))?;
+
+    Ok(())
+}
+
+fn run_relay(interval: u64, stats_file: &str) -> AnyhowResult<()> {
+    let stats = relay(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+        std::time::Duration::from_secs(interval),
     )?;
 
-    info!("policing local snapshots");
-
-    // police remote snapshots
-    actions.police_snapshots(
-        &config.backup_subvolume_path,
-        &context_remote,
-        &new_snapshot_info,
-        &config.policy_remote,
-        &timestamp.into(),
-        &config.snapshot_suffix,
-        &backup_devices,
-        &remote_mount_information,
+    info!("transferred {}", stats);
+    std::fs::write(stats_file, serde_json::to_string(&stats)?)
+        .with_context(|| format!("could not write transfer statistics to \"{}\"", stats_file))?;
+
+    Ok(())
+}
+
+fn run_throttle(bandwidth_limits: &str) -> AnyhowResult<()> {
+    let bandwidth_limits: Vec<BandwidthLimit> = serde_json::from_str(bandwidth_limits)
+        .with_context(|| format!("invalid bandwidth limits \"{}\"", bandwidth_limits))?;
+
+    throttle(
+        &mut std::io::stdin().lock(),
+        &mut std::io::stdout().lock(),
+        &bandwidth_limits,
     )?;
 
-    log::info!("backup completed");
+    Ok(())
+}
+
+fn run_command_locked(
+    command: &Command,
+    runner: &mut JobRunner,
+    job: &JobConfiguration,
+    state_directory: &str,
+) -> AnyhowResult<()> {
+    let owner = LockOwner::current()?;
+    let _local_lock = LocalLock::acquire(state_directory, job.name(), &owner)?;
+
+    runner.lock_targets(owner);
+    run_command(command, runner, job, state_directory)
+}
+
+fn run_command(
+    command: &Command,
+    runner: &mut JobRunner,
+    job: &JobConfiguration,
+    state_directory: &str,
+) -> AnyhowResult<()> {
+    match command {
+        Command::Run => runner.run()?,
+        Command::Snapshot => {
+            runner.snapshot()?;
+        }
+        Command::Send => {
+            let snapshot = runner.latest_snapshot()?;
+
+            runner.send(&snapshot)?;
+        }
+        Command::Prune {
+            only,
+            preview: true,
+            json,
+        } => {
+            let snapshot = runner.latest_snapshot()?;
+            let common_parents = runner.common_parents()?;
+            let local = match only {
+                Some(Side::Remote) => None,
+                _ => Some(runner.preview_prune_local(&snapshot, &common_parents)?),
+            };
+            let mut remote = BTreeMap::new();
+
+            if *only != Some(Side::Local) {
+                for target in runner.targets() {
+                    remote.insert(
+                        target.name(),
+                        runner.preview_prune_target(&snapshot, &common_parents, target)?,
+                    );
+                }
+            }
+
+            if *json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "job": job.name(), "local": local, "remote": remote })
+                );
+            } else {
+                if let Some(decisions) = local {
+                    println!("job \"{}\": local snapshots", job.name());
+                    print_retention_decisions(&decisions);
+                }
+
+                for (target, decisions) in remote {
+                    println!("job \"{}\": snapshots of target \"{}\"", job.name(), target);
+                    print_retention_decisions(&decisions);
+                }
+            }
+        }
+        Command::Prune { only, .. } => {
+            let snapshot = runner.latest_snapshot()?;
+
+            runner.prune(
+                &snapshot,
+                *only != Some(Side::Remote),
+                *only != Some(Side::Local),
+            )?;
+        }
+        Command::List { json } => {
+            let mut inventory = BTreeMap::new();
+
+            for target in runner.targets() {
+                inventory.insert(target.name(), runner.inventory(target)?);
+            }
+
+            if *json {
+                println!(
+                    "{}",
+                    serde_json::json!({ "job": job.name(), "targets": inventory })
+                );
+            } else {
+                for (target, entries) in inventory {
+                    println!(
+                        "job \"{}\": local snapshots and snapshots of target \"{}\"",
+                        job.name(),
+                        target
+                    );
+                    print_inventory(&entries);
+                }
+            }
+        }
+        Command::Status => {
+            let state = JobState::read(state_directory, job.name())?;
+            let stale = state.is_stale(&job.max_backup_age, &Utc::now())?;
+            let local = runner.local_snapshots()?;
+            let latest_local = local.last();
+
+            println!("job \"{}\"", job.name());
+            print_state(&state);
+            println!(
+                "  last successful run: {}{}",
+                state.last_success.map_or("-".into(), |ts| ts.to_rfc3339()),
+                match stale {
+                    true => format!(" (STALE: older than {})", job.max_backup_age),
+                    false => String::new(),
+                }
+            );
+            println!(
+                "  newest local snapshot: {}",
+                latest_local.map_or("-".into(), |(ts, _)| ts.to_rfc3339())
+            );
+
+            for target in runner.targets() {
+                let remote = runner.target_snapshots(target)?;
+
+                println!("  target \"{}\"", target.name());
+                println!(
+                    "    newest snapshot: {}",
+                    remote.last().map_or("-".into(), |(ts, _)| ts.to_rfc3339())
+                );
+                println!(
+                    "    newest local snapshot sent: {}",
+                    match latest_local {
+                        Some((_, l)) => remote.iter().any(|(_, r)| r.received_uuid == Some(l.uuid)),
+                        None => false,
+                    }
+                );
+            }
+
+            if stale {
+                return Err(anyhow!(
+                    "last successful run of job \"{}\" is older than {}",
+                    job.name(),
+                    job.max_backup_age
+                ));
+            }
+        }
+        Command::Restore {
+            snapshot,
+            target,
+            destination,
+            writable,
+            identity,
+        } => {
+            let target = match (target, runner.targets()) {
+                (Some(name), targets) => {
+                    targets.iter().find(|t| t.name() == name).with_context(|| {
+                        format!("job \"{}\" has no target \"{}\"", job.name(), name)
+                    })?
+                }
+                (None, [target]) => target,
+                (None, _) => {
+                    return Err(anyhow!(
+                        "job \"{}\" has several targets (use --target)",
+                        job.name()
+                    ))
+                }
+            };
+
+            match snapshot {
+                Some(snapshot) => {
+                    let destination = destination
+                        .as_deref()
+                        .context("no destination given (use --destination)")?;
+                    let restored = runner.restore(
+                        target,
+                        snapshot,
+                        destination,
+                        writable.as_deref(),
+                        identity.as_deref(),
+                    )?;
+
+                    println!("restored snapshot \"{}\"", restored);
+
+                    if let Some(writable) = writable {
+                        println!("created writable snapshot \"{}\"", writable);
+                    }
+                }
+                None => {
+                    println!(
+                        "job \"{}\": snapshots of target \"{}\"",
+                        job.name(),
+                        target.name()
+                    );
+
+                    for (_, sv) in runner.target_snapshots(target)? {
+                        println!("  {}", sv.btrfs_path.rsplit('/').next().unwrap_or_default());
+                    }
+                }
+            }
+        }
+        Command::RestorePath {
+            path,
+            destination,
+            before,
+            target,
+        } => {
+            let target = target
+                .as_ref()
+                .map(|name| {
+                    runner
+                        .targets()
+                        .iter()
+                        .find(|t| t.name() == name)
+                        .with_context(|| {
+                            format!("job \"{}\" has no target \"{}\"", job.name(), name)
+                        })
+                })
+                .transpose()?;
+            let source =
+                runner.restore_path(target, path, &before.unwrap_or_else(Utc::now), destination)?;
+
+            println!("copied \"{}\" to \"{}\"", source, destination);
+        }
+        Command::CheckConfig | Command::Relay { .. } | Command::Throttle { .. } => {}
+    }
 
     Ok(())
 }
+
+fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, chrono::ParseError> {
+    DateTime::parse_from_rfc3339(timestamp).map(DateTime::<Utc>::from)
+}
+
+fn print_state(state: &JobState) {
+    let last_run = match (state.last_run, state.last_run_successful) {
+        (None, _) => String::from("-"),
+        (Some(ts), true) => format!("{} (succeeded)", ts.to_rfc3339()),
+        (Some(ts), false) => format!("{} (failed)", ts.to_rfc3339()),
+    };
+
+    println!("  last run: {}", last_run);
+
+    for step in &state.last_steps {
+        println!(
+            "    {:<8}  {:<12}  {:<9}  {}",
+            step.step,
+            step.target.as_deref().unwrap_or("local"),
+            match step.outcome {
+                Outcome::Succeeded => "succeeded",
+                Outcome::Failed => "failed",
+                Outcome::Skipped => "skipped",
+            },
+            step.message.as_deref().unwrap_or("")
+        );
+    }
+
+    if let Some(error) = &state.last_error {
+        println!("  last error: {}", error);
+    }
+}
+
+fn print_inventory(entries: &[InventoryEntry]) {
+    let uuid = |uuid: Option<&Uuid>| uuid.map_or("-".into(), Uuid::to_string);
+
+    let header = [
+        "timestamp",
+        "local uuid",
+        "local parent uuid",
+        "remote uuid",
+        "remote received uuid",
+        "common",
+        "both sides",
+    ];
+
+    println!(
+        "  {:<25}  {:<36}  {:<36}  {:<36}  {:<36}  {:<6}  {}",
+        header[0], header[1], header[2], header[3], header[4], header[5], header[6]
+    );
+
+    for entry in entries {
+        println!(
+            "  {:<25}  {:<36}  {:<36}  {:<36}  {:<36}  {:<6}  {}",
+            entry.timestamp.to_rfc3339(),
+            uuid(entry.local.as_ref().map(|sv| &sv.uuid)),
+            uuid(entry.local.as_ref().and_then(|sv| sv.parent_uuid.as_ref())),
+            uuid(entry.remote.as_ref().map(|sv| &sv.uuid)),
+            uuid(
+                entry
+                    .remote
+                    .as_ref()
+                    .and_then(|sv| sv.received_uuid.as_ref())
+            ),
+            match entry.common_parent {
+                true => "yes",
+                false => "no",
+            },
+            match entry.on_both_sides {
+                true => "yes",
+                false => "no",
+            }
+        );
+    }
+}
+
+fn print_retention_decisions(decisions: &[RetentionDecision]) {
+    for decision in decisions {
+        println!(
+            "  {}  {:<6}  {:<12}  {:<36}  {}",
+            decision.timestamp.to_rfc3339(),
+            match decision.keep {
+                true => "keep",
+                false => "delete",
+            },
+            decision.bucket.as_deref().unwrap_or("-"),
+            decision.reason,
+            decision.subvolume.btrfs_path
+        );
+    }
+}
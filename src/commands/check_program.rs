@@ -0,0 +1,50 @@
+use crate::backup_error::BackupError;
+use exec_rs::{Context, Exec};
+
+pub trait CommandCheckProgram {
+    /// Check whether a program is available
+    ///
+    /// Executes `<program> --version`.
+    ///
+    /// * `program` - name of the program
+    /// * `context` - context in which to execute the command
+    ///
+    fn check_program(&mut self, program: &str, context: &Context) -> Result<(), BackupError>;
+}
+
+impl<T: Exec> CommandCheckProgram for super::Commander<T> {
+    fn check_program(&mut self, program: &str, context: &Context) -> Result<(), BackupError> {
+        self.exec
+            .exec(program, &["--version"], Some(context))
+            .map_err(|_| BackupError::ProgramNotAvailable {
+                program: program.to_string(),
+                location: super::format_context(context),
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::Commander;
+    use exec_rs::MockExec;
+
+    #[test]
+    fn check_program() {
+        let mut mock = MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .withf(|command, args, _| command == "zstd" && args == ["--version"])
+            .returning(|_, _, _| Ok(String::from("zstd v1.5.5")));
+
+        let mut commands = Commander::new_with_exec(mock);
+        let context = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+
+        assert!(commands.check_program("zstd", &context).is_ok());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::backup_error::BackupError;
+use exec_rs::{Context, Exec};
+
+pub trait CommandCopyPath {
+    /// Copy a file or directory to the local host preserving ownership, permissions, ACLs, and xattrs
+    ///
+    /// Executes `sudo cp -a <source> <destination>` for local sources and `rsync -aHAXs --numeric-ids --rsync-path="sudo rsync" <host>:<source> <destination>` for remote sources.
+    /// rsync runs as the user of the local context, so that ssh uses the configuration and keys of this user; ownership is only preserved, if this user is root.
+    ///
+    /// * `source` - path of the file or directory
+    /// * `context_source` - context of the host of the source
+    /// * `destination` - local path to copy to
+    /// * `context_local` - context in which to execute the command
+    ///
+    fn copy_path(
+        &mut self,
+        source: &str,
+        context_source: &Context,
+        destination: &str,
+        context_local: &Context,
+    ) -> Result<(), BackupError>;
+}
+
+impl<T: Exec> CommandCopyPath for super::Commander<T> {
+    fn copy_path(
+        &mut self,
+        source: &str,
+        context_source: &Context,
+        destination: &str,
+        context_local: &Context,
+    ) -> Result<(), BackupError> {
+        let (command, args): (&str, Vec<String>) = match context_source {
+            Context::Local { user: _ } => (
+                "sudo",
+                vec!["cp", "-a", source, destination]
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+            ),
+            Context::Remote { host, config } => {
+                let mut args: Vec<String> = vec!["-aHAXs", "--numeric-ids"]
+                    .into_iter()
+                    .map(String::from)
+                    .collect();
+
+                // the snapshots are only readable by root on the remote host
+                args.push(String::from("--rsync-path=sudo rsync"));
+
+                if let Some(config) = config {
+                    args.push(String::from("-e"));
+                    args.push(format!("ssh -F {}", super::shell_quote(config)));
+                }
+
+                args.push(format!("{}:{}", host, source));
+                args.push(destination.to_string());
+                ("rsync", args)
+            }
+        };
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command(command, &args, context_local)
+            );
+            return Ok(());
+        }
+
+        self.exec_command(command, &args, context_local)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::Commander;
+    use exec_rs::MockExec;
+
+    #[test]
+    fn copy_path_remote() {
+        let mut mock = MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .withf(|command, args, context| {
+                assert_eq!(command, "rsync");
+                assert_eq!(
+                    args,
+                    &[
+                        "-aHAXs",
+                        "--numeric-ids",
+                        "--rsync-path=sudo rsync",
+                        "-e",
+                        "ssh -F '/root/.ssh/config'",
+                        "backup:/mnt/backups/s/home/doc.txt",
+                        "/tmp/restore"
+                    ]
+                );
+                assert!(matches!(context, Some(Context::Local { .. })));
+                true
+            })
+            .returning(|_, _, _| Ok(String::new()));
+
+        let mut commands = Commander::new_with_exec(mock);
+
+        assert!(commands
+            .copy_path(
+                "/mnt/backups/s/home/doc.txt",
+                &Context::Remote {
+                    host: "backup".into(),
+                    config: Some("/root/.ssh/config".into()),
+                },
+                "/tmp/restore",
+                &Context::Local {
+                    user: "test".into()
+                },
+            )
+            .is_ok());
+    }
+}
@@ -35,13 +35,19 @@ impl<T: Exec> CommandDeleteSubvolume for super::Commander<T> {
             return Err(BackupError::DeleteSubvolume(String::from("subvolume cannot be deleted as its name is on the restricted names list (home, /home, /, root)")));
         }
 
-        log::info!("subvolume path: \"{}\"", subvolume);
+        log::info!(snapshot = subvolume.as_str(); "subvolume path: \"{}\"", subvolume);
 
-        self.exec.exec(
-            "sudo",
-            &["btrfs", "subvolume", "delete", &subvolume],
-            Some(context),
-        )?;
+        let args = ["btrfs", "subvolume", "delete", &subvolume];
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("sudo", &args, context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("sudo", &args, context)?;
         Ok(())
     }
 }
@@ -79,6 +85,23 @@ mod test {
             .is_ok());
     }
 
+    #[test]
+    fn delete_subvolume_dry_run() {
+        let mut commands = Commander {
+            exec: MockExec::new(),
+            dry_run: true,
+        };
+
+        assert!(commands
+            .delete_subvolume(
+                &String::from("/tmp"),
+                &Context::Local {
+                    user: "test".into()
+                }
+            )
+            .is_ok());
+    }
+
     #[test]
     fn delete_subvolume_home() {
         let mock = MockExec::new();
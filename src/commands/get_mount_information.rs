@@ -22,7 +22,7 @@ impl<T: Exec> CommandGetMountInformation for super::Commander<T> {
         &mut self,
         context: &Context,
     ) -> Result<Vec<MountInformation>, BackupError> {
-        let command_output = self.exec.exec(
+        let command_output = self.exec_command(
             "findmnt",
             &[
                 "-lnvt",
@@ -30,7 +30,7 @@ impl<T: Exec> CommandGetMountInformation for super::Commander<T> {
                 "-o",
                 "FSROOT,TARGET,FSTYPE,SOURCE,OPTIONS",
             ],
-            Some(context),
+            context,
         )?;
 
         command_output
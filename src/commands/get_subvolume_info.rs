@@ -15,6 +15,18 @@ pub trait CommandGetSubvolumeInfo {
         subvolume_path: &str,
         context: &Context,
     ) -> Result<SubvolumeInfo, BackupError>;
+    /// Check whether a subvolume is read-only
+    ///
+    /// Executes `sudo btrfs property get -ts <subvolume_path> ro`.
+    ///
+    /// * `subvolume_path` - path of the btrfs subvolume
+    /// * `context` - context in which to execute the command
+    ///
+    fn is_read_only(
+        &mut self,
+        subvolume_path: &str,
+        context: &Context,
+    ) -> Result<bool, BackupError>;
 }
 
 impl<T: Exec> CommandGetSubvolumeInfo for super::Commander<T> {
@@ -23,10 +35,10 @@ impl<T: Exec> CommandGetSubvolumeInfo for super::Commander<T> {
         subvolume_path: &str,
         context: &Context,
     ) -> Result<SubvolumeInfo, BackupError> {
-        let command_output = self.exec.exec(
+        let command_output = self.exec_command(
             "sudo",
             &["btrfs", "subvolume", "show", subvolume_path],
-            Some(context),
+            context,
         )?;
         let mut lines = command_output.lines();
         let btrfs_path_raw = lines
@@ -59,6 +71,27 @@ impl<T: Exec> CommandGetSubvolumeInfo for super::Commander<T> {
             uuid,
         })
     }
+
+    fn is_read_only(
+        &mut self,
+        subvolume_path: &str,
+        context: &Context,
+    ) -> Result<bool, BackupError> {
+        let output = self.exec_command(
+            "sudo",
+            &["btrfs", "property", "get", "-ts", subvolume_path, "ro"],
+            context,
+        )?;
+
+        match output.trim() {
+            "ro=true" => Ok(true),
+            "ro=false" => Ok(false),
+            other => Err(BackupError::SubvolumeInfoParsing(format!(
+                "unexpected read-only property: {}",
+                other
+            ))),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -172,4 +205,28 @@ mod test {
             }
         );
     }
+
+    #[test]
+    fn is_read_only() {
+        let mut mock = MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .withf(|command, args, _| {
+                command == "sudo"
+                    && args == ["btrfs", "property", "get", "-ts", "/backups/snapshot", "ro"]
+            })
+            .returning(|_, _, _| Ok(String::from("ro=false\n")));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(!commander
+            .is_read_only(
+                "/backups/snapshot",
+                &Context::Local {
+                    user: "test".into()
+                }
+            )
+            .unwrap());
+    }
 }
@@ -23,7 +23,7 @@ impl<T: Exec> CommandGetSubvolumes for super::Commander<T> {
         subvolume_path: &str,
         context: &Context,
     ) -> Result<Vec<Subvolume>, BackupError> {
-        let command_output = self.exec.exec(
+        let command_output = self.exec_command(
             "sudo",
             &[
                 "btrfs",
@@ -33,7 +33,7 @@ impl<T: Exec> CommandGetSubvolumes for super::Commander<T> {
                 "--sort=rootid",
                 subvolume_path,
             ],
-            Some(context),
+            context,
         )?;
 
         let mut subvolumes: Vec<Subvolume> = Vec::new();
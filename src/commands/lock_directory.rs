@@ -0,0 +1,202 @@
+use crate::backup_error::BackupError;
+use exec_rs::{Context, Exec};
+
+pub trait CommandLockDirectory {
+    /// Create a lock directory
+    ///
+    /// Executes `sudo mkdir <lock_path>` and `sudo touch <lock_path>/<owner>`.
+    /// As `mkdir` fails, if the directory exists already, only one process can create the lock directory.
+    /// If the owner cannot be recorded, the lock directory is removed again.
+    ///
+    /// * `lock_path` - path of the lock directory
+    /// * `owner` - owner of the lock (stored as the name of a file in the lock directory)
+    /// * `context` - context in which to execute the command
+    ///
+    fn create_lock_directory(
+        &mut self,
+        lock_path: &str,
+        owner: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+    /// Get the owners of a lock directory
+    ///
+    /// Executes `sudo ls -1A <lock_path>`.
+    ///
+    /// * `lock_path` - path of the lock directory
+    /// * `context` - context in which to execute the command
+    ///
+    fn get_lock_owners(
+        &mut self,
+        lock_path: &str,
+        context: &Context,
+    ) -> Result<Vec<String>, BackupError>;
+    /// Remove a lock directory
+    ///
+    /// Executes `sudo rm -f <lock_path>/<owner>` for every owner and `sudo rmdir <lock_path>`.
+    ///
+    /// * `lock_path` - path of the lock directory
+    /// * `owners` - owners of the lock
+    /// * `context` - context in which to execute the command
+    ///
+    fn remove_lock_directory(
+        &mut self,
+        lock_path: &str,
+        owners: &[String],
+        context: &Context,
+    ) -> Result<(), BackupError>;
+}
+
+impl<T: Exec> CommandLockDirectory for super::Commander<T> {
+    fn create_lock_directory(
+        &mut self,
+        lock_path: &str,
+        owner: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let owner_path = format!("{}/{}", lock_path, owner);
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("sudo", &["mkdir", lock_path], context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("sudo", &["mkdir", lock_path], context)?;
+
+        // a lock directory without an owner could never be detected as stale
+        if let Err(e) = self.exec_command("sudo", &["touch", &owner_path], context) {
+            if let Err(cleanup_error) = self.exec_command("sudo", &["rmdir", lock_path], context) {
+                log::warn!(
+                    "could not remove lock \"{}\" without owner: {}",
+                    lock_path,
+                    cleanup_error
+                );
+            }
+
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn get_lock_owners(
+        &mut self,
+        lock_path: &str,
+        context: &Context,
+    ) -> Result<Vec<String>, BackupError> {
+        Ok(self
+            .exec_command("sudo", &["ls", "-1A", lock_path], context)?
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect())
+    }
+
+    fn remove_lock_directory(
+        &mut self,
+        lock_path: &str,
+        owners: &[String],
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("sudo", &["rmdir", lock_path], context)
+            );
+            return Ok(());
+        }
+
+        for owner in owners {
+            let owner_path = format!("{}/{}", lock_path, owner);
+
+            self.exec_command("sudo", &["rm", "-f", &owner_path], context)?;
+        }
+
+        self.exec_command("sudo", &["rmdir", lock_path], context)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::Commander;
+    use exec_rs::MockExec;
+    use mockall::Sequence;
+
+    #[test]
+    fn create_lock_directory() {
+        let mut mock = MockExec::new();
+        let mut seq = Sequence::new();
+
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| command == "sudo" && args == ["mkdir", "/backups/.lock"])
+            .returning(|_, _, _| Ok(String::new()));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| {
+                command == "sudo" && args == ["touch", "/backups/.lock/1234@host"]
+            })
+            .returning(|_, _, _| Ok(String::new()));
+
+        let mut commands = Commander::new_with_exec(mock);
+
+        commands
+            .create_lock_directory(
+                "/backups/.lock",
+                "1234@host",
+                &Context::Local {
+                    user: "test".into(),
+                },
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn get_lock_owners() {
+        let mut mock = MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .withf(|command, args, _| command == "sudo" && args == ["ls", "-1A", "/backups/.lock"])
+            .returning(|_, _, _| Ok(String::from("1234@host\n")));
+
+        let mut commands = Commander::new_with_exec(mock);
+
+        assert_eq!(
+            commands
+                .get_lock_owners(
+                    "/backups/.lock",
+                    &Context::Local {
+                        user: "test".into(),
+                    },
+                )
+                .unwrap(),
+            vec![String::from("1234@host")]
+        );
+    }
+
+    #[test]
+    fn remove_lock_directory_dry_run() {
+        let mut commands = Commander {
+            exec: MockExec::new(),
+            dry_run: true,
+        };
+
+        commands
+            .remove_lock_directory(
+                "/backups/.lock",
+                &["1234@host".into()],
+                &Context::Local {
+                    user: "test".into(),
+                },
+            )
+            .unwrap();
+    }
+}
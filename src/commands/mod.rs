@@ -1,11 +1,17 @@
 mod get_mount_information;
 mod get_subvolumes;
-use exec_rs::{CommandExec, Exec};
+use crate::backup_error::BackupError;
+use exec_rs::{CommandExec, Context, Exec, ExecError};
+mod check_program;
+mod copy_path;
 mod delete_subvolume;
 mod get_subvolume_info;
+mod lock_directory;
+mod notify;
 mod read_link;
 mod send_snapshot;
 mod snapshot_subvolume;
+mod stream_file;
 
 pub trait Commands:
     get_mount_information::CommandGetMountInformation
@@ -15,6 +21,11 @@ pub trait Commands:
     + delete_subvolume::CommandDeleteSubvolume
     + send_snapshot::CommandSendSnapshot
     + read_link::CommandReadLink
+    + lock_directory::CommandLockDirectory
+    + check_program::CommandCheckProgram
+    + stream_file::CommandStreamFile
+    + copy_path::CommandCopyPath
+    + notify::CommandNotify
 {
 }
 
@@ -36,6 +47,12 @@ mockall::mock! {
             timestamp: &chrono::DateTime<chrono::Utc>,
             context: &exec_rs::Context,
         ) -> Result<String, crate::backup_error::BackupError>;
+        fn snapshot_subvolume_writable(
+            &mut self,
+            subvolume_path: &str,
+            snapshot_path: &str,
+            context: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
     }
     impl get_subvolumes::CommandGetSubvolumes for Commander {
         fn get_subvolumes(
@@ -50,6 +67,7 @@ mockall::mock! {
             subvolume_path: &str,
             context: &exec_rs::Context,
         ) -> Result<crate::objects::SubvolumeInfo, crate::backup_error::BackupError>;
+        fn is_read_only(&mut self, subvolume_path: &str, context: &exec_rs::Context) -> Result<bool, crate::backup_error::BackupError>;
     }
     impl delete_subvolume::CommandDeleteSubvolume for Commander {
         fn delete_subvolume(&mut self, subvolume: &str, context: &exec_rs::Context) -> Result<(), crate::backup_error::BackupError>;
@@ -62,31 +80,267 @@ mockall::mock! {
             context_local: &exec_rs::Context,
             backup_path: &str,
             context_remote: &exec_rs::Context,
-        ) -> Result<(), crate::backup_error::BackupError>;
+            options: &crate::objects::TransferOptions,
+        ) -> Result<Option<crate::objects::TransferStats>, crate::backup_error::BackupError>;
     }
     impl read_link::CommandReadLink for Commander {
         fn read_link(&mut self, path: &str, context: &exec_rs::Context) -> Result<Vec<String>, crate::backup_error::BackupError>;
     }
+    impl lock_directory::CommandLockDirectory for Commander {
+        fn create_lock_directory(
+            &mut self,
+            lock_path: &str,
+            owner: &str,
+            context: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+        fn get_lock_owners(
+            &mut self,
+            lock_path: &str,
+            context: &exec_rs::Context,
+        ) -> Result<Vec<String>, crate::backup_error::BackupError>;
+        fn remove_lock_directory(
+            &mut self,
+            lock_path: &str,
+            owners: &[String],
+            context: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+    }
+    impl check_program::CommandCheckProgram for Commander {
+        fn check_program(&mut self, program: &str, context: &exec_rs::Context) -> Result<(), crate::backup_error::BackupError>;
+    }
+    impl stream_file::CommandStreamFile for Commander {
+        fn read_manifest(&mut self, path: &str, context: &exec_rs::Context) -> Result<crate::objects::StreamManifest, crate::backup_error::BackupError>;
+        fn write_manifest(
+            &mut self,
+            path: &str,
+            manifest: &crate::objects::StreamManifest,
+            context_local: &exec_rs::Context,
+            context: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+        fn send_snapshot_to_file<'a>(
+            &mut self,
+            local_snapshot: &crate::objects::SubvolumeInfo,
+            common_parent: Option<&'a crate::objects::SubvolumeInfo>,
+            context_local: &exec_rs::Context,
+            file_path: &str,
+            context_target: &exec_rs::Context,
+            options: &crate::objects::TransferOptions,
+        ) -> Result<Option<crate::objects::TransferStats>, crate::backup_error::BackupError>;
+        fn remove_stream_file(&mut self, file_path: &str, context: &exec_rs::Context) -> Result<(), crate::backup_error::BackupError>;
+        fn remove_partial_stream_files(&mut self, path: &str, context: &exec_rs::Context) -> Result<(), crate::backup_error::BackupError>;
+        fn receive_stream_file<'a>(
+            &mut self,
+            file_path: &str,
+            context_target: &exec_rs::Context,
+            entry: &crate::objects::StreamEntry,
+            identity: Option<&'a str>,
+            destination: &str,
+            context_local: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+    }
+    impl copy_path::CommandCopyPath for Commander {
+        fn copy_path(
+            &mut self,
+            source: &str,
+            context_source: &exec_rs::Context,
+            destination: &str,
+            context_local: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+    }
+    impl notify::CommandNotify for Commander {
+        fn send_mail(
+            &mut self,
+            command: &str,
+            message: &str,
+            context: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+        fn post_json(&mut self, url: &str, payload: &str, context: &exec_rs::Context) -> Result<(), crate::backup_error::BackupError>;
+        fn notify_desktop<'a>(
+            &mut self,
+            user: Option<&'a str>,
+            urgency: &str,
+            summary: &str,
+            body: &str,
+            context: &exec_rs::Context,
+        ) -> Result<(), crate::backup_error::BackupError>;
+    }
     impl Commands for Commander {}
 }
 
+/// # Commander
+///
+/// * `exec` - command executor
+/// * `dry_run` - if set, commands modifying the system are only logged, but not executed
 pub struct Commander<T: Exec> {
     exec: T,
+    dry_run: bool,
 }
 
 #[cfg(test)]
 impl<T: Exec> Commander<T> {
     fn new_with_exec(exec: T) -> Self {
-        Self { exec }
+        Self {
+            exec,
+            dry_run: false,
+        }
     }
 }
 
-impl Default for Commander<CommandExec> {
-    fn default() -> Self {
+impl Commander<CommandExec> {
+    pub fn new(dry_run: bool) -> Self {
         Self {
             exec: CommandExec {},
+            dry_run,
         }
     }
 }
 
+impl<T: Exec> Commander<T> {
+    /// Execute a command; a failure is reported with the command line, the context, and the error of `exec_rs`
+    ///
+    /// * `command` - command to be executed
+    /// * `args` - arguments of the command
+    /// * `context` - context in which the command is executed
+    pub(super) fn exec_command(
+        &mut self,
+        command: &str,
+        args: &[&str],
+        context: &Context,
+    ) -> Result<String, BackupError> {
+        self.exec.exec(command, args, Some(context)).map_err(|e| {
+            command_error(
+                format!("{} {}", command, args.join(" ")),
+                format_context(context),
+                e,
+            )
+        })
+    }
+}
+
+/// Convert the error of a failed command
+///
+/// `exec_rs` does not expose the exit code and the standard error of a failed command as separate fields, so they are left empty and its error is kept as the source.
+///
+/// * `command` - command line of the failed command
+/// * `context` - formatted context in which the command was executed
+/// * `error` - error reported by `exec_rs`
+pub(super) fn command_error(command: String, context: String, error: ExecError) -> BackupError {
+    BackupError::Command {
+        command,
+        context,
+        exit_code: None,
+        stderr: None,
+        source: Some(error),
+    }
+}
+
+impl Default for Commander<CommandExec> {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+/// Format a command for logging
+///
+/// * `command` - command to be executed
+/// * `args` - arguments of the command
+/// * `context` - context in which the command is executed
+pub fn format_command(command: &str, args: &[&str], context: &Context) -> String {
+    format!(
+        "{}: {} {}",
+        format_context(context),
+        command,
+        args.join(" ")
+    )
+}
+
+/// Quote a value to be interpolated into a shell command
+///
+/// The value is enclosed in single quotes; single quotes within the value are replaced with `'\''`.
+///
+/// * `value` - value to be quoted
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Arguments for executing a script with `sh -c`
+///
+/// Commands in a remote context are parsed by the shell of the remote host, so the script is quoted to be passed as a single argument.
+/// Paths interpolated into the script have to be quoted with [`shell_quote`].
+///
+/// * `script` - script to be executed
+/// * `context` - context in which the script is executed
+pub fn shell_args(script: &str, context: &Context) -> Vec<String> {
+    match context {
+        Context::Local { user: _ } => vec![String::from("-c"), script.to_string()],
+        Context::Remote { host: _, config: _ } => {
+            vec![String::from("-c"), shell_quote(script)]
+        }
+    }
+}
+
+/// Format a context for logging
+///
+/// * `context` - context to be formatted
+pub fn format_context(context: &Context) -> String {
+    match context {
+        Context::Local { user } => format!("local ({})", user),
+        Context::Remote { host, config: _ } => format!("remote ({})", host),
+    }
+}
+
 impl<T: Exec> Commands for Commander<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shell_quote_value() {
+        assert_eq!(shell_quote("/backups/laptop"), "'/backups/laptop'");
+        assert_eq!(shell_quote("/backups/it's"), "'/backups/it'\\''s'");
+        assert_eq!(
+            shell_args(
+                &format!("cat > {}", shell_quote("/it's")),
+                &Context::Local {
+                    user: "test".into()
+                }
+            ),
+            ["-c", "cat > '/it'\\''s'"]
+        );
+        assert_eq!(
+            shell_args(
+                &format!("cat > {}", shell_quote("/backups")),
+                &Context::Remote {
+                    host: "nas".into(),
+                    config: None
+                }
+            ),
+            ["-c", "'cat > '\\''/backups'\\'''"]
+        );
+    }
+
+    #[test]
+    fn command_error_source() {
+        let error = CommandExec {}.exec("false", &[], None).unwrap_err();
+        let message = error.to_string();
+        let error = command_error(String::from("false"), String::from("local (test)"), error);
+
+        assert!(matches!(
+            error,
+            BackupError::Command {
+                exit_code: None,
+                stderr: None,
+                ..
+            }
+        ));
+        assert_eq!(
+            std::error::Error::source(&error).map(ToString::to_string),
+            Some(message.clone())
+        );
+        assert_eq!(
+            error.to_string(),
+            format!("command \"false\" failed on local (test): {}", message)
+        );
+    }
+}
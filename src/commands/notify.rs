@@ -0,0 +1,210 @@
+use crate::backup_error::BackupError;
+use exec_rs::{Context, Exec};
+
+pub trait CommandNotify {
+    /// Send a mail
+    ///
+    /// Executes `echo <message> | <command> -t`, so that the recipients are taken from the headers of the message.
+    ///
+    /// * `command` - `sendmail`-compatible command
+    /// * `message` - message including the headers
+    /// * `context` - context in which to execute the command
+    ///
+    fn send_mail(
+        &mut self,
+        command: &str,
+        message: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+    /// Post a JSON payload to a URL
+    ///
+    /// Executes `curl -fsS -X POST -H "Content-Type: application/json" -d <payload> <url>`.
+    ///
+    /// * `url` - URL to post to
+    /// * `payload` - JSON payload
+    /// * `context` - context in which to execute the command
+    ///
+    fn post_json(&mut self, url: &str, payload: &str, context: &Context)
+        -> Result<(), BackupError>;
+    /// Show a desktop notification
+    ///
+    /// Executes `notify-send -u <urgency> -a backup-btrfs <summary> <body>`.
+    /// The notification is shown in the session of the given user; the session bus is found in `/run/user/<uid>/bus`.
+    ///
+    /// * `user` - user whose session shows the notification (the user of the context, if not set)
+    /// * `urgency` - urgency of the notification (`low`, `normal`, or `critical`)
+    /// * `summary` - summary of the notification
+    /// * `body` - body of the notification
+    /// * `context` - context in which to execute the command
+    ///
+    fn notify_desktop(
+        &mut self,
+        user: Option<&str>,
+        urgency: &str,
+        summary: &str,
+        body: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+}
+
+impl<T: Exec> CommandNotify for super::Commander<T> {
+    fn send_mail(
+        &mut self,
+        command: &str,
+        message: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let stages = [
+            ("echo", vec![message.to_string()], context),
+            (command, vec![String::from("-t")], context),
+        ];
+
+        self.exec_stages(&stages)
+    }
+
+    fn post_json(
+        &mut self,
+        url: &str,
+        payload: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let args = [
+            "-fsS",
+            "-X",
+            "POST",
+            "-H",
+            "Content-Type: application/json",
+            "-d",
+            payload,
+            url,
+        ];
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("curl", &args, context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("curl", &args, context)?;
+        Ok(())
+    }
+
+    fn notify_desktop(
+        &mut self,
+        user: Option<&str>,
+        urgency: &str,
+        summary: &str,
+        body: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let notify_args = ["-u", urgency, "-a", "backup-btrfs", summary, body];
+        let (command, args): (&str, Vec<&str>) = match user {
+            // the session bus of the user is required to reach the desktop session
+            Some(user) => (
+                "sudo",
+                [
+                    "-u",
+                    user,
+                    "sh",
+                    "-c",
+                    "DBUS_SESSION_BUS_ADDRESS=unix:path=/run/user/$(id -u)/bus exec notify-send \"$@\"",
+                    "notify-send",
+                ]
+                .iter()
+                .chain(&notify_args)
+                .copied()
+                .collect(),
+            ),
+            None => ("notify-send", notify_args.to_vec()),
+        };
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command(command, &args, context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command(command, &args, context)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::Commander;
+    use exec_rs::MockExec;
+
+    #[test]
+    fn send_mail() {
+        let mut mock = MockExec::new();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(|commands| {
+                assert_eq!(commands.len(), 2);
+                assert_eq!(commands[0].0, "echo");
+                assert_eq!(commands[0].1, &["To: admin@example.com\n\nfailed"]);
+                assert_eq!(commands[1].0, "/usr/sbin/sendmail");
+                assert_eq!(commands[1].1, &["-t"]);
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let mut commands = Commander::new_with_exec(mock);
+
+        assert!(commands
+            .send_mail(
+                "/usr/sbin/sendmail",
+                "To: admin@example.com\n\nfailed",
+                &Context::Local {
+                    user: "test".into()
+                },
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn notify_desktop_user() {
+        let mut mock = MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .withf(|command, args, _| {
+                assert_eq!(command, "sudo");
+                assert_eq!(&args[..3], &["-u", "hannes", "sh"]);
+                assert_eq!(
+                    &args[5..],
+                    &[
+                        "notify-send",
+                        "-u",
+                        "critical",
+                        "-a",
+                        "backup-btrfs",
+                        "summary",
+                        "body"
+                    ]
+                );
+                true
+            })
+            .returning(|_, _, _| Ok(String::new()));
+
+        let mut commands = Commander::new_with_exec(mock);
+
+        assert!(commands
+            .notify_desktop(
+                Some("hannes"),
+                "critical",
+                "summary",
+                "body",
+                &Context::Local {
+                    user: "test".into()
+                },
+            )
+            .is_ok());
+    }
+}
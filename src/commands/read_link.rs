@@ -18,8 +18,7 @@ pub trait CommandReadLink {
 impl<T: Exec> CommandReadLink for super::Commander<T> {
     fn read_link(&mut self, path: &str, context: &Context) -> Result<Vec<String>, BackupError> {
         let command_output = self
-            .exec
-            .exec("readlink", &["-f", path], Some(context))?
+            .exec_command("readlink", &["-f", path], context)?
             .trim()
             .to_string();
 
@@ -1,5 +1,6 @@
 use crate::{backup_error::BackupError, objects::*};
 use exec_rs::{Context, Exec};
+use std::path::{Path, PathBuf};
 
 pub trait CommandSendSnapshot {
     /// Send a local snapshot to a remote host
@@ -9,6 +10,15 @@ pub trait CommandSendSnapshot {
     /// * `context_local` - context to execute the local commands
     /// * `backup_path` - base path to store the snapshot on the remote host
     /// * `context_remote` - context to execute the remote commands
+    /// * `options` - transfer options
+    ///
+    /// With compression, the stream is compressed locally and decompressed in the same remote command as `btrfs receive` (i.e. `sh -c '<decompressor> | sudo btrfs receive <backup_path>'`), so that only compressed data is transferred.
+    /// Compression is not applied, if the remote context is a local one.
+    /// With a relay, the relay is inserted directly after `btrfs send` and the size and duration of the transfer reported by it are returned.
+    /// With bandwidth limits of the relay, the throttle stage of the relay is inserted after the compressor, so that the limit applying at the current time of day applies to the transferred data.
+    /// With a rate limit or a buffer size, `mbuffer` is inserted locally as the last stage before the receiving command, so that the limit applies to the transferred data.
+    /// With a staging path, the (decompressed) stream is appended to a file in the staging path on the target, which is received with `btrfs receive -f` once it is complete.
+    /// An interrupted transfer is resumed by skipping the bytes already staged.
     ///
     fn send_snapshot<'a>(
         &mut self,
@@ -17,7 +27,8 @@ pub trait CommandSendSnapshot {
         context_local: &Context,
         backup_path: &str,
         context_remote: &Context,
-    ) -> Result<(), BackupError>;
+        options: &TransferOptions,
+    ) -> Result<Option<TransferStats>, BackupError>;
 }
 
 impl<T: Exec> CommandSendSnapshot for super::Commander<T> {
@@ -28,29 +39,417 @@ impl<T: Exec> CommandSendSnapshot for super::Commander<T> {
         context_local: &Context,
         backup_path: &str,
         context_remote: &Context,
-    ) -> Result<(), BackupError> {
+        options: &TransferOptions,
+    ) -> Result<Option<TransferStats>, BackupError> {
         log::debug!(
+            snapshot = local_snapshot.fs_path.as_str(), uuid:% = local_snapshot.uuid;
             "sending snapshot: \"{}\" to \"{}\"",
             local_snapshot.fs_path,
             backup_path
         );
-        let mut args = vec!["btrfs", "send"];
+        // stages of the pipeline
+        let mut stages: Vec<(&str, Vec<String>, &Context)> = vec![(
+            "sudo",
+            super::Commander::<T>::send_args(local_snapshot, common_parent),
+            context_local,
+        )];
+        let stats_file = super::Commander::<T>::stats_file(local_snapshot);
+        let staging_file = options.staging_path.as_ref().map(|staging_path| {
+            format!(
+                "{}/{}",
+                staging_path,
+                TransferOptions::staging_file_name(local_snapshot, common_parent)
+            )
+        });
 
-        if let Some(parent_snapshot) = common_parent {
-            args.push("-p");
-            args.push(&parent_snapshot.fs_path);
+        // the send stream is deterministic, so the bytes staged by previous attempts can be skipped
+        if let Some(staging_file) = &staging_file {
+            let mut offset = self.get_staged_bytes(staging_file, context_remote)?;
+
+            if offset > 0
+                && !self.staged_stream_matches(
+                    local_snapshot,
+                    common_parent,
+                    context_local,
+                    staging_file,
+                    offset,
+                    context_remote,
+                )?
+            {
+                log::warn!(
+                    snapshot = local_snapshot.fs_path.as_str(), uuid:% = local_snapshot.uuid;
+                    "staged stream \"{}\" does not match snapshot \"{}\", restarting transfer",
+                    staging_file,
+                    local_snapshot.fs_path
+                );
+                self.remove_staged_stream(staging_file, context_remote)?;
+                offset = 0;
+            }
+
+            if offset > 0 {
+                log::info!(
+                    snapshot = local_snapshot.fs_path.as_str(), uuid:% = local_snapshot.uuid;
+                    "resuming transfer of snapshot \"{}\" after {} bytes",
+                    local_snapshot.fs_path,
+                    offset
+                );
+                stages.push((
+                    "tail",
+                    vec![String::from("-c"), format!("+{}", offset + 1)],
+                    context_local,
+                ));
+            }
+        }
+
+        if let Some(relay) = &options.relay {
+            stages.push((
+                &relay.program,
+                relay.args(&stats_file.to_string_lossy()),
+                context_local,
+            ));
+        }
+
+        let compression = match (&options.compression, context_remote) {
+            (Some(compression), Context::Remote { host: _, config: _ }) => Some(compression),
+            _ => None,
+        };
+
+        if let Some(compression) = compression {
+            stages.push((
+                compression.program(),
+                compression.compress_args(),
+                context_local,
+            ));
+        }
+
+        if let Some(relay) = &options.relay {
+            if let Some(throttle_args) = relay.throttle_args()? {
+                stages.push((&relay.program, throttle_args, context_local));
+            }
+        }
+
+        if let Some(buffer_args) = options.buffer_args() {
+            stages.push((TransferOptions::BUFFER_PROGRAM, buffer_args, context_local));
         }
 
-        args.push(&local_snapshot.fs_path);
+        let receive = match &staging_file {
+            Some(staging_file) => format!("cat >> {}", super::shell_quote(staging_file)),
+            None => format!("sudo btrfs receive {}", super::shell_quote(backup_path)),
+        };
 
-        self.exec.exec_piped(&[
-            ("sudo", &args, Some(context_local)),
-            (
+        match (compression, &staging_file) {
+            (Some(compression), _) => stages.push((
+                "sh",
+                super::shell_args(
+                    &format!(
+                        "{} {} | {}",
+                        compression.program(),
+                        compression.decompress_args().join(" "),
+                        receive
+                    ),
+                    context_remote,
+                ),
+                context_remote,
+            )),
+            (None, Some(_)) => stages.push((
+                "sh",
+                super::shell_args(&receive, context_remote),
+                context_remote,
+            )),
+            (None, None) => stages.push((
                 "sudo",
-                &["btrfs", "receive", backup_path],
-                Some(context_remote),
+                vec![
+                    String::from("btrfs"),
+                    String::from("receive"),
+                    backup_path.to_string(),
+                ],
+                context_remote,
+            )),
+        }
+
+        if self.dry_run {
+            self.exec_stages(&stages)?;
+
+            if let Some(staging_file) = &staging_file {
+                log::info!(
+                    "dry run: skipping \"{}\"",
+                    super::format_command(
+                        "sudo",
+                        &["btrfs", "receive", "-f", staging_file, backup_path],
+                        context_remote
+                    )
+                );
+            }
+
+            return Ok(None);
+        }
+
+        let res = self.exec_stages(&stages);
+        // the statistics file is removed even if the transfer failed
+        let stats = options
+            .relay
+            .as_ref()
+            .and_then(|_| super::Commander::<T>::read_transfer_stats(&stats_file));
+
+        res?;
+
+        if let Some(staging_file) = &staging_file {
+            self.receive_staged_stream(staging_file, backup_path, context_remote)?;
+        }
+
+        Ok(stats)
+    }
+}
+
+impl<T: Exec> super::Commander<T> {
+    /// Arguments of `sudo` for sending a snapshot
+    ///
+    /// * `local_snapshot` - snapshot to be sent
+    /// * `common_parent` - parent of an incremental transfer
+    pub(super) fn send_args(
+        local_snapshot: &SubvolumeInfo,
+        common_parent: Option<&SubvolumeInfo>,
+    ) -> Vec<String> {
+        let mut args = vec![String::from("btrfs"), String::from("send")];
+
+        if let Some(parent_snapshot) = common_parent {
+            args.push(String::from("-p"));
+            args.push(parent_snapshot.fs_path.clone());
+        }
+
+        args.push(local_snapshot.fs_path.clone());
+        args
+    }
+
+    /// Path of the file the relay writes the statistics of a transfer to
+    ///
+    /// * `local_snapshot` - snapshot to be sent
+    pub(super) fn stats_file(local_snapshot: &SubvolumeInfo) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "backup-btrfs-transfer-{}-{}.json",
+            std::process::id(),
+            local_snapshot.uuid
+        ))
+    }
+
+    /// Read and remove the statistics written by the relay
+    ///
+    /// * `stats_file` - path of the statistics file
+    pub(super) fn read_transfer_stats(stats_file: &Path) -> Option<TransferStats> {
+        let content = std::fs::read_to_string(stats_file);
+        let _ = std::fs::remove_file(stats_file);
+
+        match content
+            .map_err(BackupError::from)
+            .and_then(|content| Ok(serde_json::from_str::<TransferStats>(&content)?))
+        {
+            Ok(stats) => Some(stats),
+            Err(e) => {
+                log::warn!("could not read transfer statistics: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Execute the stages of a pipeline; in dry-run mode, the stages are only logged
+    ///
+    /// * `stages` - commands, arguments, and contexts of the stages
+    pub(super) fn exec_stages(
+        &mut self,
+        stages: &[(&str, Vec<String>, &Context)],
+    ) -> Result<(), BackupError> {
+        let stage_args: Vec<Vec<&str>> = stages
+            .iter()
+            .map(|(_, args, _)| args.iter().map(String::as_str).collect())
+            .collect();
+        let commands: Vec<(&str, &[&str], Option<&Context>)> = stages
+            .iter()
+            .zip(&stage_args)
+            .map(|((command, _, context), args)| (*command, args.as_slice(), Some(*context)))
+            .collect();
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                stages
+                    .iter()
+                    .zip(&stage_args)
+                    .map(|((command, _, context), args)| super::format_command(
+                        command, args, context
+                    ))
+                    .collect::<Vec<String>>()
+                    .join("\" | \"")
+            );
+            return Ok(());
+        }
+
+        let start = std::time::Instant::now();
+
+        self.exec.exec_piped(&commands).map_err(|e| {
+            let mut contexts: Vec<String> = Vec::new();
+
+            for (_, _, context) in stages {
+                let context = super::format_context(context);
+
+                if !contexts.contains(&context) {
+                    contexts.push(context);
+                }
+            }
+
+            super::command_error(
+                stages
+                    .iter()
+                    .zip(&stage_args)
+                    .map(|((command, _, _), args)| format!("{} {}", command, args.join(" ")))
+                    .collect::<Vec<String>>()
+                    .join(" | "),
+                contexts.join(", "),
+                e,
+            )
+        })?;
+        log::debug!(
+            duration = start.elapsed().as_secs_f64();
+            "executed pipeline of {} stages", stages.len()
+        );
+        Ok(())
+    }
+
+    /// Get the number of bytes of a staged send stream; returns 0, if the file does not exist
+    ///
+    /// * `staging_file` - path of the staged send stream
+    /// * `context` - context of the target
+    fn get_staged_bytes(
+        &mut self,
+        staging_file: &str,
+        context: &Context,
+    ) -> Result<u64, BackupError> {
+        let output = self.exec_command(
+            "sh",
+            &super::shell_args(
+                &format!(
+                    "if [ ! -e {0} ]; then echo 0; else stat -c %s {0}; fi",
+                    super::shell_quote(staging_file)
+                ),
+                context,
+            )
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>(),
+            context,
+        )?;
+
+        output
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| BackupError::StagedStreamSize(output.trim().to_string()))
+    }
+
+    /// Check whether a staged send stream is a prefix of the send stream of a snapshot
+    ///
+    /// Compares the SHA-256 checksum of the staged stream with the checksum of the first bytes of a new send stream.
+    ///
+    /// * `local_snapshot` - snapshot to be sent
+    /// * `common_parent` - parent of an incremental transfer
+    /// * `context_local` - context to execute the local commands
+    /// * `staging_file` - path of the staged send stream
+    /// * `staged_bytes` - number of bytes of the staged send stream
+    /// * `context` - context of the target
+    fn staged_stream_matches(
+        &mut self,
+        local_snapshot: &SubvolumeInfo,
+        common_parent: Option<&SubvolumeInfo>,
+        context_local: &Context,
+        staging_file: &str,
+        staged_bytes: u64,
+        context: &Context,
+    ) -> Result<bool, BackupError> {
+        let send = super::Commander::<T>::send_args(local_snapshot, common_parent)
+            .iter()
+            .map(|arg| super::shell_quote(arg))
+            .collect::<Vec<String>>()
+            .join(" ");
+        // `btrfs send` fails once `head` closes the pipe, so only the exit status of the checksum is relevant
+        let mut local_args = vec![String::from("sh")];
+
+        local_args.extend(super::shell_args(
+            &format!(
+                "{} 2>/dev/null | head -c {} | sha256sum",
+                send, staged_bytes
             ),
-        ])?;
+            context_local,
+        ));
+
+        let local_checksum = self.exec_command(
+            "sudo",
+            &local_args.iter().map(String::as_str).collect::<Vec<&str>>(),
+            context_local,
+        )?;
+        let staged_checksum = self.exec_command(
+            "sh",
+            &super::shell_args(
+                &format!("sha256sum < {}", super::shell_quote(staging_file)),
+                context,
+            )
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<&str>>(),
+            context,
+        )?;
+
+        Ok(
+            match (
+                local_checksum.split_whitespace().next(),
+                staged_checksum.split_whitespace().next(),
+            ) {
+                (Some(local), Some(staged)) => local == staged,
+                _ => false,
+            },
+        )
+    }
+
+    /// Remove a staged send stream, which does not match the snapshot to be sent
+    ///
+    /// * `staging_file` - path of the staged send stream
+    /// * `context` - context of the target
+    fn remove_staged_stream(
+        &mut self,
+        staging_file: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("rm", &["-f", staging_file], context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("rm", &["-f", staging_file], context)?;
+        Ok(())
+    }
+
+    /// Receive a completely staged send stream and remove it
+    ///
+    /// The stream is removed even if receiving fails, as a complete stream, which cannot be received, is corrupt.
+    ///
+    /// * `staging_file` - path of the staged send stream
+    /// * `backup_path` - path to receive the snapshot in
+    /// * `context` - context of the target
+    fn receive_staged_stream(
+        &mut self,
+        staging_file: &str,
+        backup_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let res = self.exec_command(
+            "sudo",
+            &["btrfs", "receive", "-f", staging_file, backup_path],
+            context,
+        );
+
+        self.exec_command("rm", &["-f", staging_file], context)?;
+        res?;
+
         Ok(())
     }
 }
@@ -105,7 +504,77 @@ mod test {
                 None,
                 &context_local,
                 "/backups/to_be_received",
-                &context_remote
+                &context_remote,
+                &TransferOptions::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn send_snapshot_local_target() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::nil(),
+        };
+        let mut mock = MockExec::new();
+        let mock_context_local = context_local.clone();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(move |commands| {
+                assert_eq!(commands.len(), 2);
+                assert_eq!(commands[0].2, Some(&mock_context_local));
+                assert_eq!(commands[1].1, &["btrfs", "receive", "/media/usb/backups"]);
+                assert_eq!(commands[1].2, Some(&mock_context_local));
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/media/usb/backups",
+                &context_local,
+                &TransferOptions::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn send_snapshot_dry_run() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: Some("/tmp/ident".into()),
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::nil(),
+        };
+        let mut commander = Commander {
+            exec: MockExec::new(),
+            dry_run: true,
+        };
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/backups/to_be_received",
+                &context_remote,
+                &TransferOptions::default()
             )
             .is_ok());
     }
@@ -168,7 +637,410 @@ mod test {
                 Some(&snapshot_parent),
                 &context_local,
                 "/backups/to_be_received",
-                &context_remote
+                &context_remote,
+                &TransferOptions::default()
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn send_snapshot_compression() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::nil(),
+        };
+        let options = TransferOptions {
+            compression: Some(Compression {
+                algorithm: CompressionAlgorithm::Zstd,
+                level: Some(3),
+            }),
+            ..TransferOptions::default()
+        };
+        let mut mock = MockExec::new();
+        let mock_context_local = context_local.clone();
+        let mock_context_remote = context_remote.clone();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(move |commands| {
+                assert_eq!(commands.len(), 3);
+                assert_eq!(commands[1].0, "zstd");
+                assert_eq!(commands[1].1, &["-c", "-3"]);
+                assert_eq!(commands[1].2, Some(&mock_context_local));
+                assert_eq!(commands[2].0, "sh");
+                assert_eq!(
+                    commands[2].1,
+                    &[
+                        "-c",
+                        r#"'zstd -d -c | sudo btrfs receive '\''/backups'\'''"#
+                    ]
+                );
+                assert_eq!(commands[2].2, Some(&mock_context_remote));
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/backups",
+                &context_remote,
+                &options
+            )
+            .is_ok());
+
+        // compression is pointless for local targets
+        let mut mock = MockExec::new();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(|commands| commands.len() == 2)
+            .returning(|_| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/backups",
+                &context_local,
+                &options
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn send_snapshot_rate_limit() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::nil(),
+        };
+        let options = TransferOptions {
+            compression: Some(Compression {
+                algorithm: CompressionAlgorithm::Lz4,
+                level: None,
+            }),
+            rate_limit: Some("10M".into()),
+            buffer_size: Some("256M".into()),
+            relay: None,
+            staging_path: None,
+            encryption: None,
+        };
+        let mut mock = MockExec::new();
+        let mock_context_local = context_local.clone();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(move |commands| {
+                assert_eq!(commands.len(), 4);
+                assert_eq!(commands[1].0, "lz4");
+                assert_eq!(commands[2].0, "mbuffer");
+                assert_eq!(commands[2].1, &["-q", "-m", "256M", "-r", "10M"]);
+                assert_eq!(commands[2].2, Some(&mock_context_local));
+                assert_eq!(commands[3].0, "sh");
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/backups",
+                &context_remote,
+                &options
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn send_snapshot_relay() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::from_u128(15),
+        };
+        let options = TransferOptions {
+            relay: Some(Relay {
+                program: "/usr/bin/backup-btrfs".into(),
+                interval: 30,
+                bandwidth_limits: vec![BandwidthLimit {
+                    rate: "1M".into(),
+                    from: None,
+                    until: None,
+                }],
+            }),
+            ..TransferOptions::default()
+        };
+        let mut mock = MockExec::new();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(|commands| {
+                assert_eq!(commands.len(), 4);
+                assert_eq!(commands[1].0, "/usr/bin/backup-btrfs");
+                assert_eq!(
+                    &commands[1].1[0..4],
+                    &["relay", "--interval", "30", "--stats-file"]
+                );
+                assert_eq!(commands[2].0, "/usr/bin/backup-btrfs");
+                assert_eq!(
+                    commands[2].1,
+                    &[
+                        "throttle",
+                        "--bandwidth-limits",
+                        r#"[{"rate":"1M","from":null,"until":null}]"#
+                    ]
+                );
+                true
+            })
+            .returning(|commands| {
+                // simulate the relay writing its statistics
+                std::fs::write(commands[1].1[4], r#"{ "bytes": 1024, "seconds": 2.0 }"#).unwrap();
+                Ok(String::new())
+            });
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert_eq!(
+            commander
+                .send_snapshot(
+                    &snapshot_local,
+                    None,
+                    &context_local,
+                    "/backups",
+                    &context_remote,
+                    &options
+                )
+                .unwrap(),
+            Some(TransferStats {
+                bytes: 1024,
+                seconds: 2.0
+            })
+        );
+    }
+
+    #[test]
+    fn send_snapshot_staging_resume() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::from_u128(1),
+        };
+        let snapshot_parent = SubvolumeInfo {
+            fs_path: "/snapshots/parent".into(),
+            btrfs_path: "/root/snapshots/parent".into(),
+            uuid: Uuid::from_u128(2),
+        };
+        let options = TransferOptions {
+            staging_path: Some("/backups/.staging".into()),
+            ..TransferOptions::default()
+        };
+        let staging_file = format!(
+            "/backups/.staging/{}_{}.stream",
+            Uuid::from_u128(1),
+            Uuid::from_u128(2)
+        );
+        let mut mock = MockExec::new();
+        let mut seq = mockall::Sequence::new();
+        let expected_file = staging_file.clone();
+
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |command, args, _| {
+                command == "sh"
+                    && args
+                        == [
+                            "-c",
+                            &format!(
+                                r#"'if [ ! -e '\''{0}'\'' ]; then echo 0; else stat -c %s '\''{0}'\''; fi'"#,
+                                expected_file
+                            ),
+                        ]
+            })
+            .returning(|_, _, _| Ok(String::from("1024\n")));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| {
+                command == "sudo"
+                    && args
+                        == [
+                            "sh",
+                            "-c",
+                            "'btrfs' 'send' '-p' '/snapshots/parent' '/snapshots/to_be_sent' 2>/dev/null | head -c 1024 | sha256sum",
+                        ]
+            })
+            .returning(|_, _, _| Ok(String::from("0123abcd  -\n")));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| command == "sh" && args[1].contains("sha256sum"))
+            .returning(|_, _, _| Ok(String::from("0123abcd  -\n")));
+
+        let expected_file = staging_file.clone();
+
+        mock.expect_exec_piped()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |commands| {
+                assert_eq!(commands.len(), 3);
+                assert_eq!(commands[1].0, "tail");
+                assert_eq!(commands[1].1, &["-c", "+1025"]);
+                assert_eq!(commands[2].0, "sh");
+                assert_eq!(
+                    commands[2].1,
+                    &["-c", &format!(r#"'cat >> '\''{}'\'''"#, expected_file)]
+                );
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let expected_file = staging_file.clone();
+
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |command, args, _| {
+                command == "sudo" && args == ["btrfs", "receive", "-f", &expected_file, "/backups"]
+            })
+            .returning(|_, _, _| Ok(String::new()));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |command, args, _| command == "rm" && args == ["-f", &staging_file])
+            .returning(|_, _, _| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                Some(&snapshot_parent),
+                &context_local,
+                "/backups",
+                &context_remote,
+                &options
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn send_snapshot_staging_mismatch() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "host".into(),
+            config: None,
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::from_u128(1),
+        };
+        let options = TransferOptions {
+            staging_path: Some("/backups/.staging".into()),
+            ..TransferOptions::default()
+        };
+        let staging_file = format!("/backups/.staging/{}.stream", Uuid::from_u128(1));
+        let mut mock = MockExec::new();
+        let mut seq = mockall::Sequence::new();
+
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| command == "sh" && args[1].contains("stat -c %s"))
+            .returning(|_, _, _| Ok(String::from("1024\n")));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, _, _| command == "sudo")
+            .returning(|_, _, _| Ok(String::from("0123abcd  -\n")));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| command == "sh" && args[1].contains("sha256sum"))
+            .returning(|_, _, _| Ok(String::from("4567ef01  -\n")));
+
+        let expected_file = staging_file.clone();
+
+        // the staged stream is removed and the transfer starts from the beginning
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |command, args, _| command == "rm" && args == ["-f", &expected_file])
+            .returning(|_, _, _| Ok(String::new()));
+        mock.expect_exec_piped()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|commands| {
+                assert_eq!(commands.len(), 2);
+                assert_eq!(commands[1].0, "sh");
+                true
+            })
+            .returning(|_| Ok(String::new()));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, _, _| command == "sudo")
+            .returning(|_, _, _| Ok(String::new()));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |command, args, _| command == "rm" && args == ["-f", &staging_file])
+            .returning(|_, _, _| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/backups",
+                &context_remote,
+                &options
             )
             .is_ok());
     }
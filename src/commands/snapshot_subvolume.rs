@@ -1,7 +1,7 @@
 use crate::backup_error::BackupError;
 use chrono::{DateTime, SecondsFormat, Utc};
 use exec_rs::{Context, Exec};
-use log::debug;
+use log::{debug, info};
 use std::path::PathBuf;
 
 pub trait CommandSnapshotSubvolume {
@@ -26,6 +26,21 @@ pub trait CommandSnapshotSubvolume {
         timestamp: &DateTime<Utc>,
         context: &Context,
     ) -> Result<String, BackupError>;
+
+    /// Create a writable snapshot of a subvolume
+    ///
+    /// Executes `sudo btrfs subvolume snapshot <subvolume_path> <snapshot_path>`.
+    ///
+    /// * `subvolume_path` - path of the subvolume (e.g. a read-only snapshot)
+    /// * `snapshot_path` - path of the new snapshot
+    /// * `context` - context in which to execute the command
+    ///
+    fn snapshot_subvolume_writable(
+        &mut self,
+        subvolume_path: &str,
+        snapshot_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
 }
 
 impl<T: Exec> CommandSnapshotSubvolume for super::Commander<T> {
@@ -54,25 +69,57 @@ impl<T: Exec> CommandSnapshotSubvolume for super::Commander<T> {
                     "could not construct snapshot_path",
                 )))?;
 
-        debug!("creating snapshot of \"{subvolume_path}\" in location \"{snapshot_path}\"");
+        debug!(snapshot = snapshot_path; "creating snapshot of \"{subvolume_path}\" in location \"{snapshot_path}\"");
+
+        let args = [
+            "btrfs",
+            "subvolume",
+            "snapshot",
+            "-r",
+            subvolume_path,
+            snapshot_path,
+        ];
 
-        self.exec.exec(
-            "sudo",
-            &[
-                "btrfs",
-                "subvolume",
-                "snapshot",
-                "-r",
-                subvolume_path,
-                snapshot_path,
-            ],
-            Some(context),
-        )?;
+        if self.dry_run {
+            info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("sudo", &args, context)
+            );
+            return Ok(snapshot_path.to_string());
+        }
 
-        debug!("created snapshot successfully");
+        self.exec_command("sudo", &args, context)?;
+
+        debug!(snapshot = snapshot_path; "created snapshot successfully");
 
         Ok(snapshot_path.to_string())
     }
+
+    fn snapshot_subvolume_writable(
+        &mut self,
+        subvolume_path: &str,
+        snapshot_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let args = [
+            "btrfs",
+            "subvolume",
+            "snapshot",
+            subvolume_path,
+            snapshot_path,
+        ];
+
+        if self.dry_run {
+            info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("sudo", &args, context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("sudo", &args, context)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -125,4 +172,23 @@ mod test {
             "/snapshots/2022-11-02T12:13:14Z_test_test"
         );
     }
+
+    #[test]
+    fn snapshot_subvolume_dry_run() {
+        let context = Context::Local {
+            user: "test".into(),
+        };
+        let timestamp = DateTime::parse_from_rfc3339("2022-11-02T12:13:14Z").unwrap();
+        let mut commands = Commander {
+            exec: MockExec::new(),
+            dry_run: true,
+        };
+
+        assert_eq!(
+            commands
+                .snapshot_subvolume("/home", "/snapshots", "test", &timestamp.into(), &context,)
+                .unwrap(),
+            "/snapshots/2022-11-02T12:13:14Z_test"
+        );
+    }
 }
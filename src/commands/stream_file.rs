@@ -0,0 +1,531 @@
+use crate::{backup_error::BackupError, objects::*};
+use exec_rs::{Context, Exec};
+
+pub trait CommandStreamFile {
+    /// Read the manifest of a file target; returns an empty manifest, if the manifest does not exist
+    ///
+    /// * `path` - directory of the file target
+    /// * `context` - context of the target
+    ///
+    fn read_manifest(
+        &mut self,
+        path: &str,
+        context: &Context,
+    ) -> Result<StreamManifest, BackupError>;
+
+    /// Write the manifest of a file target
+    ///
+    /// The manifest is written to a local file, which is piped to a temporary file on the target; the temporary file is then renamed, so that the manifest is never left incomplete.
+    ///
+    /// * `path` - directory of the file target
+    /// * `manifest` - manifest to be written
+    /// * `context_local` - context to execute the local commands
+    /// * `context` - context of the target
+    ///
+    fn write_manifest(
+        &mut self,
+        path: &str,
+        manifest: &StreamManifest,
+        context_local: &Context,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+
+    /// Send a local snapshot to a file
+    ///
+    /// The stream is compressed and encrypted locally according to the options and written to `<file_path>.part`, which is renamed to `<file_path>` once the transfer is complete.
+    /// Returns the size and duration of the transfer, if reported by the relay.
+    ///
+    /// * `local_snapshot` - snapshot to be sent
+    /// * `common_parent` - parent snapshot (must be stored on the target as well)
+    /// * `context_local` - context to execute the local commands
+    /// * `file_path` - path of the file on the target
+    /// * `context_target` - context of the target
+    /// * `options` - transfer options
+    ///
+    fn send_snapshot_to_file<'a>(
+        &mut self,
+        local_snapshot: &SubvolumeInfo,
+        common_parent: Option<&'a SubvolumeInfo>,
+        context_local: &Context,
+        file_path: &str,
+        context_target: &Context,
+        options: &TransferOptions,
+    ) -> Result<Option<TransferStats>, BackupError>;
+
+    /// Remove a stream file
+    ///
+    /// * `file_path` - path of the file
+    /// * `context` - context of the target
+    ///
+    fn remove_stream_file(&mut self, file_path: &str, context: &Context)
+        -> Result<(), BackupError>;
+
+    /// Remove the incomplete stream files (`*.part`) of interrupted transfers
+    ///
+    /// * `path` - directory of the file target
+    /// * `context` - context of the target
+    ///
+    fn remove_partial_stream_files(
+        &mut self,
+        path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError>;
+
+    /// Receive a stream file into a local directory
+    ///
+    /// The stream is read on the target and decrypted and decompressed on the local host.
+    ///
+    /// * `file_path` - path of the stream file on the target
+    /// * `context_target` - context of the target
+    /// * `entry` - manifest entry of the stream
+    /// * `identity` - identity file for decrypting the stream (age only)
+    /// * `destination` - local directory to receive the snapshot in
+    /// * `context_local` - context to execute the local commands
+    ///
+    fn receive_stream_file<'a>(
+        &mut self,
+        file_path: &str,
+        context_target: &Context,
+        entry: &StreamEntry,
+        identity: Option<&'a str>,
+        destination: &str,
+        context_local: &Context,
+    ) -> Result<(), BackupError>;
+}
+
+impl<T: Exec> CommandStreamFile for super::Commander<T> {
+    fn read_manifest(
+        &mut self,
+        path: &str,
+        context: &Context,
+    ) -> Result<StreamManifest, BackupError> {
+        let manifest_path = format!("{}/{}", path, StreamManifest::FILE_NAME);
+        let args = super::shell_args(
+            &format!(
+                "if [ -f {0} ]; then cat {0}; fi",
+                super::shell_quote(&manifest_path)
+            ),
+            context,
+        );
+        let output = self.exec_command(
+            "sh",
+            &args.iter().map(String::as_str).collect::<Vec<&str>>(),
+            context,
+        )?;
+
+        match output.trim().is_empty() {
+            true => Ok(StreamManifest::default()),
+            false => Ok(serde_json::from_str(&output)?),
+        }
+    }
+
+    fn write_manifest(
+        &mut self,
+        path: &str,
+        manifest: &StreamManifest,
+        context_local: &Context,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let manifest_path = format!("{}/{}", path, StreamManifest::FILE_NAME);
+        // the manifest is passed through a local file, as it may exceed the maximum length of an argument
+        let local_file =
+            std::env::temp_dir().join(format!("backup-btrfs-manifest-{}.json", std::process::id()));
+
+        std::fs::write(&local_file, serde_json::to_string(manifest)?)?;
+
+        let stages = vec![
+            (
+                "cat",
+                vec![local_file.to_string_lossy().to_string()],
+                context_local,
+            ),
+            (
+                "sh",
+                super::shell_args(
+                    &format!(
+                        "cat > {0} && test -s {0} && mv {0} {1}",
+                        super::shell_quote(&format!("{}.tmp", manifest_path)),
+                        super::shell_quote(&manifest_path)
+                    ),
+                    context,
+                ),
+                context,
+            ),
+        ];
+        let res = self.exec_stages(&stages);
+
+        let _ = std::fs::remove_file(&local_file);
+
+        res
+    }
+
+    fn send_snapshot_to_file(
+        &mut self,
+        local_snapshot: &SubvolumeInfo,
+        common_parent: Option<&SubvolumeInfo>,
+        context_local: &Context,
+        file_path: &str,
+        context_target: &Context,
+        options: &TransferOptions,
+    ) -> Result<Option<TransferStats>, BackupError> {
+        log::debug!(
+            "sending snapshot: \"{}\" to file \"{}\"",
+            local_snapshot.fs_path,
+            file_path
+        );
+
+        // stages of the pipeline
+        let mut stages: Vec<(&str, Vec<String>, &Context)> = vec![(
+            "sudo",
+            super::Commander::<T>::send_args(local_snapshot, common_parent),
+            context_local,
+        )];
+        let stats_file = super::Commander::<T>::stats_file(local_snapshot);
+        let partial_file_path = format!("{}.part", file_path);
+
+        if let Some(relay) = &options.relay {
+            stages.push((
+                &relay.program,
+                relay.args(&stats_file.to_string_lossy()),
+                context_local,
+            ));
+        }
+
+        if let Some(compression) = &options.compression {
+            stages.push((
+                compression.program(),
+                compression.compress_args(),
+                context_local,
+            ));
+        }
+
+        if let Some(encryption) = &options.encryption {
+            stages.push((
+                encryption.tool.program(),
+                encryption.encrypt_args(),
+                context_local,
+            ));
+        }
+
+        if let Some(relay) = &options.relay {
+            if let Some(throttle_args) = relay.throttle_args()? {
+                stages.push((&relay.program, throttle_args, context_local));
+            }
+        }
+
+        if let Some(buffer_args) = options.buffer_args() {
+            stages.push((TransferOptions::BUFFER_PROGRAM, buffer_args, context_local));
+        }
+
+        stages.push((
+            "sh",
+            super::shell_args(
+                &format!("cat > {}", super::shell_quote(&partial_file_path)),
+                context_target,
+            ),
+            context_target,
+        ));
+
+        if self.dry_run {
+            self.exec_stages(&stages)?;
+            return Ok(None);
+        }
+
+        let res = self.exec_stages(&stages);
+        // the statistics file is removed even if the transfer failed
+        let stats = options
+            .relay
+            .as_ref()
+            .and_then(|_| super::Commander::<T>::read_transfer_stats(&stats_file));
+
+        res?;
+        self.exec_command("mv", &[&partial_file_path, file_path], context_target)?;
+
+        Ok(stats)
+    }
+
+    fn remove_stream_file(
+        &mut self,
+        file_path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("rm", &["-f", file_path], context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("rm", &["-f", file_path], context)?;
+        Ok(())
+    }
+
+    fn remove_partial_stream_files(
+        &mut self,
+        path: &str,
+        context: &Context,
+    ) -> Result<(), BackupError> {
+        let args = super::shell_args(
+            &format!("rm -f {}/*.part", super::shell_quote(path)),
+            context,
+        );
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+        if self.dry_run {
+            log::info!(
+                "dry run: skipping \"{}\"",
+                super::format_command("sh", &args, context)
+            );
+            return Ok(());
+        }
+
+        self.exec_command("sh", &args, context)?;
+        Ok(())
+    }
+
+    fn receive_stream_file(
+        &mut self,
+        file_path: &str,
+        context_target: &Context,
+        entry: &StreamEntry,
+        identity: Option<&str>,
+        destination: &str,
+        context_local: &Context,
+    ) -> Result<(), BackupError> {
+        let mut stages: Vec<(&str, Vec<String>, &Context)> =
+            vec![("cat", vec![file_path.to_string()], context_target)];
+
+        if let Some(encryption) = &entry.encryption {
+            stages.push((
+                encryption.program(),
+                encryption.decrypt_args(identity),
+                context_local,
+            ));
+        }
+
+        if let Some(compression) = &entry.compression {
+            stages.push((
+                compression.program(),
+                compression.decompress_args(),
+                context_local,
+            ));
+        }
+
+        stages.push((
+            "sudo",
+            vec![
+                String::from("btrfs"),
+                String::from("receive"),
+                destination.to_string(),
+            ],
+            context_local,
+        ));
+
+        self.exec_stages(&stages)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::commands::Commander;
+    use exec_rs::MockExec;
+    use uuid::Uuid;
+
+    #[test]
+    fn read_manifest() {
+        let context = Context::Remote {
+            host: "nas".into(),
+            config: None,
+        };
+        let mut mock = MockExec::new();
+
+        mock.expect_exec()
+            .once()
+            .withf(|command, args, _| {
+                command == "sh"
+                    && args
+                        == [
+                            "-c",
+                            r#"'if [ -f '\''/nas/laptop/manifest.json'\'' ]; then cat '\''/nas/laptop/manifest.json'\''; fi'"#,
+                        ]
+            })
+            .returning(|_, _, _| Ok(String::from("\n")));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert_eq!(
+            commander.read_manifest("/nas/laptop", &context).unwrap(),
+            StreamManifest::default()
+        );
+    }
+
+    #[test]
+    fn send_snapshot_to_file() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "nas".into(),
+            config: None,
+        };
+        let snapshot_local = SubvolumeInfo {
+            fs_path: "/snapshots/to_be_sent".into(),
+            btrfs_path: "/btrfs/path".into(),
+            uuid: Uuid::from_u128(1),
+        };
+        let options = TransferOptions {
+            compression: Some(Compression {
+                algorithm: CompressionAlgorithm::Zstd,
+                level: None,
+            }),
+            encryption: Some(Encryption {
+                tool: EncryptionTool::Age,
+                recipients: vec!["age1key".into()],
+            }),
+            ..TransferOptions::default()
+        };
+        let mut mock = MockExec::new();
+        let mut seq = mockall::Sequence::new();
+        let mock_context_remote = context_remote.clone();
+
+        mock.expect_exec_piped()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(move |commands| {
+                assert_eq!(commands.len(), 4);
+                assert_eq!(commands[0].1, &["btrfs", "send", "/snapshots/to_be_sent"]);
+                assert_eq!(commands[1].0, "zstd");
+                assert_eq!(commands[2].0, "age");
+                assert_eq!(commands[2].1, &["-r", "age1key"]);
+                assert_eq!(commands[3].0, "sh");
+                assert_eq!(
+                    commands[3].1,
+                    &["-c", r#"'cat > '\''/nas/laptop/s.btrfs.part'\'''"#]
+                );
+                assert_eq!(commands[3].2, Some(&mock_context_remote));
+                true
+            })
+            .returning(|_| Ok(String::new()));
+        mock.expect_exec()
+            .once()
+            .in_sequence(&mut seq)
+            .withf(|command, args, _| {
+                command == "mv" && args == ["/nas/laptop/s.btrfs.part", "/nas/laptop/s.btrfs"]
+            })
+            .returning(|_, _, _| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .send_snapshot_to_file(
+                &snapshot_local,
+                None,
+                &context_local,
+                "/nas/laptop/s.btrfs",
+                &context_remote,
+                &options
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn receive_stream_file() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "nas".into(),
+            config: None,
+        };
+        let entry = StreamEntry {
+            snapshot: "/snapshots/s".into(),
+            uuid: Uuid::from_u128(2),
+            parent_uuid: Some(Uuid::from_u128(1)),
+            file: "s.btrfs.zst.age".into(),
+            compression: Some(CompressionAlgorithm::Zstd),
+            encryption: Some(EncryptionTool::Age),
+        };
+        let mut mock = MockExec::new();
+        let mock_context_remote = context_remote.clone();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(move |commands| {
+                assert_eq!(commands.len(), 4);
+                assert_eq!(commands[0].0, "cat");
+                assert_eq!(commands[0].2, Some(&mock_context_remote));
+                assert_eq!(commands[1].1, &["-d", "-i", "/root/key.txt"]);
+                assert_eq!(commands[2].1, &["-d", "-c"]);
+                assert_eq!(commands[3].1, &["btrfs", "receive", "/restore"]);
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .receive_stream_file(
+                "/nas/laptop/s.btrfs.zst.age",
+                &context_remote,
+                &entry,
+                Some("/root/key.txt"),
+                "/restore",
+                &context_local,
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn write_manifest() {
+        let context_local = Context::Local {
+            user: "test".into(),
+        };
+        let context_remote = Context::Remote {
+            host: "nas".into(),
+            config: None,
+        };
+        let mut mock = MockExec::new();
+
+        mock.expect_exec_piped()
+            .once()
+            .withf(|commands| {
+                assert_eq!(commands.len(), 2);
+                assert_eq!(commands[0].0, "cat");
+                assert_eq!(
+                    std::fs::read_to_string(commands[0].1[0]).unwrap(),
+                    serde_json::to_string(&StreamManifest::default()).unwrap()
+                );
+                assert_eq!(commands[1].0, "sh");
+                true
+            })
+            .returning(|_| Ok(String::new()));
+
+        let mut commander = Commander::new_with_exec(mock);
+
+        assert!(commander
+            .write_manifest(
+                "/nas/laptop",
+                &StreamManifest::default(),
+                &context_local,
+                &context_remote
+            )
+            .is_ok());
+    }
+
+    #[test]
+    fn write_manifest_dry_run() {
+        let context = Context::Local {
+            user: "test".into(),
+        };
+        let mut commander = Commander {
+            exec: MockExec::new(),
+            dry_run: true,
+        };
+
+        assert!(commander
+            .write_manifest("/nas", &StreamManifest::default(), &context, &context)
+            .is_ok());
+    }
+}
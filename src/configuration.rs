@@ -1,22 +1,228 @@
 use crate::custom_duration::CustomDuration;
-use anyhow::{Context, Result};
+use crate::logging::LogFormat;
+use crate::objects::{BandwidthLimit, Compression, Encryption, Notifier, TransferOptions};
+use crate::retention::RetentionPolicy;
+use anyhow::{anyhow, Context, Result};
+use chrono::NaiveTime;
+use exec_rs::Context as ExecContext;
 use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fs::File;
 
+/// # Configuration
+///
+/// The configuration file either contains a list of jobs (`{ "jobs": [...] }`) or, for backwards compatibility, the fields of a single job at the top level.
+///
+/// * `state_directory` - directory in which the state of the jobs is persisted
+/// * `metrics_file` - file the metrics of the jobs are written to after every run in the Prometheus text format (e.g. for the textfile collector of the node exporter)
+/// * `notifications` - notifiers informed about the outcome of the runs of all jobs
+/// * `log_format` - format of the log output (can be overridden on the command line)
+/// * `jobs` - backup jobs
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "ConfigurationFile")]
 pub struct Configuration {
+    pub state_directory: String,
+    pub metrics_file: Option<String>,
+    pub notifications: Vec<Notifier>,
+    pub log_format: Option<LogFormat>,
+    pub jobs: Vec<JobConfiguration>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConfigurationFile {
+    #[serde(default = "default_state_directory")]
+    state_directory: String,
+    #[serde(default)]
+    metrics_file: Option<String>,
+    #[serde(default)]
+    notifications: Vec<Notifier>,
+    #[serde(default)]
+    log_format: Option<LogFormat>,
+    #[serde(default)]
+    jobs: Option<Vec<JobConfiguration>>,
+    // fields of a single job at the top level
+    #[serde(flatten)]
+    job: Map<String, Value>,
+}
+
+fn default_state_directory() -> String {
+    String::from("/var/lib/backup-btrfs")
+}
+
+impl TryFrom<ConfigurationFile> for Configuration {
+    type Error = serde_json::Error;
+
+    fn try_from(file: ConfigurationFile) -> Result<Self, Self::Error> {
+        let jobs = match file.jobs {
+            Some(jobs) => jobs,
+            None => vec![serde_json::from_value(Value::Object(file.job))?],
+        };
+
+        Ok(Configuration {
+            state_directory: file.state_directory,
+            metrics_file: file.metrics_file,
+            notifications: file.notifications,
+            log_format: file.log_format,
+            jobs,
+        })
+    }
+}
+
+/// # JobConfiguration
+///
+/// Configuration of a single backup job, i.e. one source subvolume and the targets it is backed up to.
+/// For backwards compatibility, the fields of a single target can also be placed directly in the job.
+/// The last successful run is reported as stale by the `status` command, once it is older than `max_backup_age` (defaults to one day).
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "JobConfigurationFile")]
+pub struct JobConfiguration {
+    pub name: Option<String>,
     pub source_subvolume_path: String,
     pub snapshot_device: String,
     pub snapshot_subvolume_path: String,
     pub snapshot_path: String,
     pub snapshot_suffix: String,
     pub user_local: String,
-    pub policy_local: Vec<CustomDuration>,
-    pub config_ssh: ConfigurationSsh,
+    pub policy_local: RetentionPolicy,
+    pub targets: Vec<TargetConfiguration>,
+    pub max_backup_age: CustomDuration,
+}
+
+#[derive(Debug, Deserialize)]
+struct JobConfigurationFile {
+    name: Option<String>,
+    source_subvolume_path: String,
+    snapshot_device: String,
+    snapshot_subvolume_path: String,
+    snapshot_path: String,
+    snapshot_suffix: String,
+    user_local: String,
+    policy_local: RetentionPolicy,
+    #[serde(default)]
+    targets: Vec<TargetConfiguration>,
+    // fields of a single target in the job
+    #[serde(flatten)]
+    target: Map<String, Value>,
+    #[serde(default = "default_max_backup_age")]
+    max_backup_age: CustomDuration,
+}
+
+fn default_max_backup_age() -> CustomDuration {
+    CustomDuration::days(1)
+}
+
+impl TryFrom<JobConfigurationFile> for JobConfiguration {
+    type Error = String;
+
+    fn try_from(file: JobConfigurationFile) -> Result<Self, Self::Error> {
+        let name = file
+            .name
+            .clone()
+            .unwrap_or_else(|| file.snapshot_suffix.clone());
+        let target = match file.target.is_empty() {
+            true => None,
+            false => Some(
+                serde_json::from_value::<TargetConfiguration>(Value::Object(file.target))
+                    .map_err(|e| format!("invalid target in job \"{}\": {}", name, e))?,
+            ),
+        };
+
+        Ok(JobConfiguration {
+            name: file.name,
+            source_subvolume_path: file.source_subvolume_path,
+            snapshot_device: file.snapshot_device,
+            snapshot_subvolume_path: file.snapshot_subvolume_path,
+            snapshot_path: file.snapshot_path,
+            snapshot_suffix: file.snapshot_suffix,
+            user_local: file.user_local,
+            policy_local: file.policy_local,
+            targets: target.into_iter().chain(file.targets).collect(),
+            max_backup_age: file.max_backup_age,
+        })
+    }
+}
+
+/// # TargetConfiguration
+///
+/// Configuration of a backup target of a job.
+///
+/// * `name` - name of the target; defaults to the ssh host or "local"
+/// * `kind` - type of the target (`type` in the configuration file)
+/// * `config_ssh` - ssh configuration; if omitted, the target is a filesystem on the local host
+/// * `backup_device` - device the backups reside on (btrfs targets only)
+/// * `backup_subvolume_path` - path of the subvolume containing the backups (btrfs targets only)
+/// * `backup_path` - path the snapshots are received in (the directory of the stream files for file targets)
+/// * `policy` - retention policy of the target (`policy_remote` is accepted as well)
+/// * `catch_up` - send all local snapshots missing on the target instead of only the newest one
+/// * `compression` - compression of the send stream (only applied to remote targets)
+/// * `bandwidth_limits` - limits of the transfer rate; the first limit applying at the current time of day is used, which changes during a transfer
+/// * `buffer_size` - size of the buffer in the send pipeline (e.g. "256M")
+/// * `progress_interval` - interval between progress reports of transfers in seconds (defaults to 60)
+/// * `retries` - number of times a failed transfer is retried (defaults to 0)
+/// * `retry_delay` - delay before the first retry in seconds, which doubles with every further retry (defaults to 30)
+/// * `staging_path` - directory on the target to stage the send stream in, so that interrupted transfers can be resumed
+/// * `encryption` - encryption of the stream files (file targets only)
+/// * `max_chain_length` - maximum number of streams in a chain of incremental streams starting with a full stream (file targets only; defaults to 30)
+#[derive(Debug, Deserialize)]
+pub struct TargetConfiguration {
+    pub name: Option<String>,
+    #[serde(default, rename = "type")]
+    pub kind: TargetKind,
+    #[serde(default)]
+    pub config_ssh: Option<ConfigurationSsh>,
+    #[serde(default)]
     pub backup_device: String,
+    #[serde(default)]
     pub backup_subvolume_path: String,
     pub backup_path: String,
-    pub policy_remote: Vec<CustomDuration>,
+    #[serde(alias = "policy_remote")]
+    pub policy: RetentionPolicy,
+    #[serde(default)]
+    pub catch_up: bool,
+    #[serde(default)]
+    pub compression: Option<Compression>,
+    #[serde(default)]
+    pub bandwidth_limits: Vec<BandwidthLimit>,
+    #[serde(default)]
+    pub buffer_size: Option<String>,
+    #[serde(default = "default_progress_interval")]
+    pub progress_interval: u64,
+    #[serde(default)]
+    pub retries: u32,
+    #[serde(default = "default_retry_delay")]
+    pub retry_delay: u64,
+    #[serde(default)]
+    pub staging_path: Option<String>,
+    #[serde(default)]
+    pub encryption: Option<Encryption>,
+    #[serde(default = "default_max_chain_length")]
+    pub max_chain_length: usize,
+}
+
+/// # TargetKind
+///
+/// * `Btrfs` - snapshots are received by a btrfs filesystem
+/// * `File` - send streams are stored as files on any filesystem
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetKind {
+    #[default]
+    Btrfs,
+    File,
+}
+
+fn default_max_chain_length() -> usize {
+    30
+}
+
+fn default_progress_interval() -> u64 {
+    60
+}
+
+fn default_retry_delay() -> u64 {
+    30
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,6 +231,29 @@ pub struct ConfigurationSsh {
     pub config: Option<String>,
 }
 
+/// Check whether a name can be used as a component of a path (e.g. of the state and lock files of a job)
+///
+/// Names must not be empty, `.`, or `..` and may only contain ASCII letters, digits, `-`, `_`, `.`, and `@`.
+///
+/// * `name` - name to be checked
+fn is_safe_name(name: &str) -> bool {
+    !matches!(name, "" | "." | "..")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.@".contains(c))
+}
+
+/// Check whether a path equals another path or contains it
+///
+/// * `parent` - path that might contain the other path
+/// * `path` - path to be checked
+fn path_contains(parent: &str, path: &str) -> bool {
+    let parent = parent.trim_end_matches('/');
+    let path = path.trim_end_matches('/');
+
+    path == parent || path.starts_with(&format!("{}/", parent))
+}
+
 impl Configuration {
     pub fn read_from_file(filepath: &str) -> Result<Self> {
         let file = File::open(filepath).context(format!(
@@ -34,4 +263,588 @@ impl Configuration {
 
         Ok(serde_json::from_reader(file)?)
     }
+
+    /// Check the configuration for consistency
+    pub fn validate(&self) -> Result<()> {
+        if self.jobs.is_empty() {
+            return Err(anyhow!("configuration does not contain any jobs"));
+        }
+
+        // the textfile collector only reads files with the extension ".prom"
+        if let Some(metrics_file) = &self.metrics_file {
+            if !metrics_file.ends_with(".prom") {
+                return Err(anyhow!(
+                    "metrics file \"{}\" does not have the extension \".prom\"",
+                    metrics_file
+                ));
+            }
+        }
+
+        for notifier in &self.notifications {
+            match notifier {
+                Notifier::Sendmail { to, .. } if to.is_empty() => {
+                    return Err(anyhow!("sendmail notifier does not have any recipients"));
+                }
+                Notifier::Webhook { url, .. }
+                    if !url.starts_with("http://") && !url.starts_with("https://") =>
+                {
+                    return Err(anyhow!("webhook URL \"{}\" is not an HTTP URL", url));
+                }
+                _ => (),
+            }
+        }
+
+        let mut names = HashSet::new();
+
+        for job in &self.jobs {
+            if !is_safe_name(job.name()) {
+                return Err(anyhow!(
+                    "job name \"{}\" is not valid (allowed are ASCII letters, digits, \"-\", \"_\", \".\", and \"@\")",
+                    job.name()
+                ));
+            }
+
+            if !names.insert(job.name()) {
+                return Err(anyhow!("job name \"{}\" is not unique", job.name()));
+            }
+
+            if job.targets.is_empty() {
+                return Err(anyhow!("job \"{}\" does not have any targets", job.name()));
+            }
+
+            if let RetentionPolicy::Counts(counts) = &job.policy_local {
+                if !counts.keeps_snapshots() {
+                    return Err(anyhow!(
+                        "local retention policy of job \"{}\" does not keep any snapshots",
+                        job.name()
+                    ));
+                }
+            }
+
+            let mut target_names = HashSet::new();
+
+            for target in &job.targets {
+                if !is_safe_name(target.name()) {
+                    return Err(anyhow!(
+                        "target name \"{}\" of job \"{}\" is not valid (allowed are ASCII letters, digits, \"-\", \"_\", \".\", and \"@\")",
+                        target.name(),
+                        job.name()
+                    ));
+                }
+
+                if !target_names.insert(target.name()) {
+                    return Err(anyhow!(
+                        "target name \"{}\" of job \"{}\" is not unique",
+                        target.name(),
+                        job.name()
+                    ));
+                }
+
+                if let RetentionPolicy::Counts(counts) = &target.policy {
+                    if !counts.keeps_snapshots() {
+                        return Err(anyhow!(
+                            "retention policy of target \"{}\" of job \"{}\" does not keep any snapshots",
+                            target.name(),
+                            job.name()
+                        ));
+                    }
+                }
+
+                if let Some(Compression {
+                    algorithm,
+                    level: Some(level),
+                }) = &target.compression
+                {
+                    if !algorithm.levels().contains(level) {
+                        return Err(anyhow!(
+                            "compression level {} of target \"{}\" of job \"{}\" is not in the range {}-{} of {}",
+                            level,
+                            target.name(),
+                            job.name(),
+                            algorithm.levels().start(),
+                            algorithm.levels().end(),
+                            algorithm.program()
+                        ));
+                    }
+                }
+
+                if let Some(limit) = target
+                    .bandwidth_limits
+                    .iter()
+                    .find(|limit| limit.bytes_per_second().is_none())
+                {
+                    return Err(anyhow!(
+                        "invalid rate \"{}\" of a bandwidth limit of target \"{}\" of job \"{}\"",
+                        limit.rate,
+                        target.name(),
+                        job.name()
+                    ));
+                }
+
+                match target.kind {
+                    TargetKind::Btrfs
+                        if target.backup_device.is_empty()
+                            || target.backup_subvolume_path.is_empty() =>
+                    {
+                        return Err(anyhow!(
+                            "btrfs target \"{}\" of job \"{}\" requires a backup device and a backup subvolume path",
+                            target.name(),
+                            job.name()
+                        ));
+                    }
+                    // incomplete snapshots in the target path are deleted before every transfer
+                    TargetKind::Btrfs
+                        if target.config_ssh.is_none()
+                            && target.backup_device == job.snapshot_device
+                            && path_contains(
+                                &target.backup_subvolume_path,
+                                &job.snapshot_subvolume_path,
+                            ) =>
+                    {
+                        return Err(anyhow!(
+                            "backup subvolume path \"{}\" of target \"{}\" of job \"{}\" must not contain the local snapshots",
+                            target.backup_subvolume_path,
+                            target.name(),
+                            job.name()
+                        ));
+                    }
+                    TargetKind::File if target.max_chain_length == 0 => {
+                        return Err(anyhow!(
+                            "the maximum chain length of file target \"{}\" of job \"{}\" must not be zero",
+                            target.name(),
+                            job.name()
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Select jobs by name
+    ///
+    /// * `names` - names of the jobs to select; all jobs are selected, if the list is empty
+    pub fn select_jobs(&self, names: &[String]) -> Result<Vec<&JobConfiguration>> {
+        if names.is_empty() {
+            return Ok(self.jobs.iter().collect());
+        }
+
+        names
+            .iter()
+            .map(|name| {
+                self.jobs
+                    .iter()
+                    .find(|job| job.name() == name)
+                    .ok_or_else(|| anyhow!("could not find job \"{}\"", name))
+            })
+            .collect()
+    }
+}
+
+impl JobConfiguration {
+    /// Name of the job; defaults to the snapshot suffix, if no name is configured
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.snapshot_suffix)
+    }
+
+    /// Context for executing commands on the local host
+    pub fn context_local(&self) -> ExecContext {
+        ExecContext::Local {
+            user: self.user_local.clone(),
+        }
+    }
+
+    /// Context for executing commands on a backup target
+    ///
+    /// Without an ssh configuration, the target is a filesystem on the local host and the commands are executed locally.
+    ///
+    /// * `target` - backup target
+    pub fn context_target(&self, target: &TargetConfiguration) -> ExecContext {
+        match &target.config_ssh {
+            Some(config_ssh) => ExecContext::Remote {
+                host: config_ssh.host.clone(),
+                config: config_ssh.config.clone(),
+            },
+            None => self.context_local(),
+        }
+    }
+}
+
+impl TargetConfiguration {
+    /// Options for sending snapshots to the target
+    ///
+    /// * `time` - local time of day at which the transfer starts
+    pub fn transfer_options(&self, time: &NaiveTime) -> TransferOptions {
+        TransferOptions {
+            compression: self.compression.clone(),
+            rate_limit: self
+                .bandwidth_limits
+                .iter()
+                .find(|limit| limit.applies_at(time))
+                .map(|limit| limit.rate.clone()),
+            buffer_size: self.buffer_size.clone(),
+            relay: None,
+            staging_path: self.staging_path.clone(),
+            encryption: self.encryption.clone(),
+        }
+    }
+
+    /// Delay before a retry of a failed transfer (exponential backoff)
+    ///
+    /// * `retry` - number of the retry (starting at 1)
+    pub fn retry_delay(&self, retry: u32) -> std::time::Duration {
+        std::time::Duration::from_secs(
+            self.retry_delay
+                .saturating_mul(2u64.saturating_pow(retry.saturating_sub(1))),
+        )
+    }
+
+    /// Name of the target; defaults to the ssh host or "local", if no name is configured
+    pub fn name(&self) -> &str {
+        match (&self.name, &self.config_ssh) {
+            (Some(name), _) => name,
+            (None, Some(config_ssh)) => &config_ssh.host,
+            (None, None) => "local",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::CompressionAlgorithm;
+    use crate::retention::RetentionCounts;
+
+    const JOB: &str = r#"{
+        "source_subvolume_path": "/home",
+        "snapshot_device": "/dev/mapper/data",
+        "snapshot_subvolume_path": "/snapshots",
+        "snapshot_path": "/snapshots",
+        "snapshot_suffix": "home",
+        "user_local": "root",
+        "policy_local": [{ "hours": 1 }],
+        "config_ssh": { "host": "backup", "config": "/root/.ssh/config" },
+        "backup_device": "/dev/mapper/backup",
+        "backup_subvolume_path": "/backups",
+        "backup_path": "/backups/laptop",
+        "policy_remote": [{ "days": 1 }, { "weeks": 4 }]
+    }"#;
+
+    #[test]
+    fn read_malformed_single_job() {
+        // the error of the target is reported instead of dropping the target
+        let error =
+            serde_json::from_str::<Configuration>(&JOB.replace("backup_path", "backup_pth"))
+                .unwrap_err()
+                .to_string();
+
+        assert!(error.contains("invalid target in job \"home\""));
+        assert!(error.contains("missing field `backup_path`"));
+
+        let error = serde_json::from_str::<Configuration>(&JOB.replace(
+            r#""backup_device""#,
+            r#""retries": "three", "backup_device""#,
+        ))
+        .unwrap_err()
+        .to_string();
+
+        assert!(error.contains("invalid target in job \"home\""));
+        assert!(error.contains("invalid type: string \"three\""));
+    }
+
+    #[test]
+    fn read_single_job() {
+        let config: Configuration = serde_json::from_str(JOB).unwrap();
+
+        assert_eq!(config.jobs.len(), 1);
+        assert_eq!(config.jobs[0].name(), "home");
+        assert_eq!(config.jobs[0].targets.len(), 1);
+        assert_eq!(config.jobs[0].targets[0].name(), "backup");
+        assert!(matches!(
+            config.jobs[0].context_target(&config.jobs[0].targets[0]),
+            ExecContext::Remote { host, .. } if host == "backup"
+        ));
+        assert_eq!(config.state_directory, "/var/lib/backup-btrfs");
+    }
+
+    #[test]
+    fn read_jobs() {
+        let srv = JOB
+            .replacen("{", r#"{ "name": "srv", "#, 1)
+            .replace("\"/home\"", "\"/srv\"");
+        let config: Configuration = serde_json::from_str(&format!(
+            r#"{{ "state_directory": "/tmp/state", "metrics_file": "/tmp/backup.prom", "notifications": [{{ "type": "webhook", "url": "https://example.com/hook" }}], "log_format": "json", "jobs": [{}, {}] }}"#,
+            JOB, srv
+        ))
+        .unwrap();
+
+        assert_eq!(config.state_directory, "/tmp/state");
+        assert_eq!(config.metrics_file.as_deref(), Some("/tmp/backup.prom"));
+        assert_eq!(config.notifications.len(), 1);
+        assert_eq!(config.log_format, Some(LogFormat::Json));
+        assert_eq!(config.jobs.len(), 2);
+        assert_eq!(config.jobs[0].name(), "home");
+        assert_eq!(config.jobs[1].name(), "srv");
+        assert_eq!(config.jobs[1].source_subvolume_path, "/srv");
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.select_jobs(&["srv".into()]).unwrap()[0].source_subvolume_path,
+            "/srv"
+        );
+        assert!(config.select_jobs(&["var".into()]).is_err());
+    }
+
+    #[test]
+    fn validate_duplicate_names() {
+        let config: Configuration =
+            serde_json::from_str(&format!(r#"{{ "jobs": [{}, {}] }}"#, JOB, JOB)).unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_names() {
+        for name in ["../etc", "a/b", "..", ""] {
+            let config: Configuration = serde_json::from_str(&JOB.replace(
+                r#""source_subvolume_path""#,
+                &format!(r#""name": "{}", "source_subvolume_path""#, name),
+            ))
+            .unwrap();
+
+            assert!(config.validate().is_err(), "job name \"{}\"", name);
+        }
+
+        let config: Configuration = serde_json::from_str(&JOB.replace(
+            r#""source_subvolume_path""#,
+            r#""name": "home-2.daily", "source_subvolume_path""#,
+        ))
+        .unwrap();
+
+        assert!(config.validate().is_ok());
+
+        let config: Configuration =
+            serde_json::from_str(&JOB.replace(r#""host": "backup""#, r#""host": "backup/../..""#))
+                .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn read_local_target() {
+        let config: Configuration = serde_json::from_str(&JOB.replace(
+            r#""config_ssh": { "host": "backup", "config": "/root/.ssh/config" },"#,
+            "",
+        ))
+        .unwrap();
+
+        assert!(config.jobs[0].targets[0].config_ssh.is_none());
+        assert_eq!(config.jobs[0].targets[0].name(), "local");
+        assert!(matches!(
+            config.jobs[0].context_target(&config.jobs[0].targets[0]),
+            ExecContext::Local { user } if user == "root"
+        ));
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn validate_local_target_containing_snapshots() {
+        let config: Configuration = serde_json::from_str(
+            &JOB.replace(
+                r#""config_ssh": { "host": "backup", "config": "/root/.ssh/config" },"#,
+                "",
+            )
+            .replace("/dev/mapper/backup", "/dev/mapper/data")
+            .replace(
+                r#""backup_subvolume_path": "/backups""#,
+                r#""backup_subvolume_path": "/""#,
+            ),
+        )
+        .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn read_count_policy() {
+        let config: Configuration = serde_json::from_str(&JOB.replace(
+            r#"[{ "days": 1 }, { "weeks": 4 }]"#,
+            r#"{ "daily": 7, "weekly": 4, "monthly": 12, "yearly": 5 }"#,
+        ))
+        .unwrap();
+
+        assert!(matches!(
+            config.jobs[0].policy_local,
+            RetentionPolicy::Durations(_)
+        ));
+        assert!(matches!(
+            config.jobs[0].targets[0].policy,
+            RetentionPolicy::Counts(RetentionCounts { monthly: 12, .. })
+        ));
+        assert!(config.validate().is_ok());
+
+        // counts, which are all zero, do not keep any snapshots
+        let config: Configuration = serde_json::from_str(
+            &JOB.replace(r#"[{ "days": 1 }, { "weeks": 4 }]"#, r#"{ "min_keep": 3 }"#),
+        )
+        .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn read_targets() {
+        let config: Configuration = serde_json::from_str(
+            r#"{
+                "name": "home",
+                "source_subvolume_path": "/home",
+                "snapshot_device": "/dev/mapper/data",
+                "snapshot_subvolume_path": "/snapshots",
+                "snapshot_path": "/snapshots",
+                "snapshot_suffix": "home",
+                "user_local": "root",
+                "policy_local": [{ "hours": 1 }],
+                "targets": [
+                    {
+                        "config_ssh": { "host": "backup" },
+                        "compression": { "algorithm": "zstd", "level": 3 },
+                        "bandwidth_limits": [{ "rate": "1M", "from": "08:00", "until": "18:00" }],
+                        "buffer_size": "256M",
+                        "retries": 3,
+                        "retry_delay": 10,
+                        "staging_path": "/backups/.staging",
+                        "backup_device": "/dev/mapper/backup",
+                        "backup_subvolume_path": "/backups",
+                        "backup_path": "/backups/laptop",
+                        "policy": [{ "days": 1 }]
+                    },
+                    {
+                        "name": "usb",
+                        "backup_device": "/dev/sdb",
+                        "backup_subvolume_path": "/media/usb",
+                        "backup_path": "/media/usb/laptop",
+                        "policy": { "daily": 7 },
+                        "catch_up": true
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(config.jobs[0].name(), "home");
+        assert_eq!(
+            config.jobs[0]
+                .targets
+                .iter()
+                .map(|t| t.name())
+                .collect::<Vec<&str>>(),
+            vec!["backup", "usb"]
+        );
+        assert!(!config.jobs[0].targets[0].catch_up);
+        assert_eq!(
+            config.jobs[0].targets[0].transfer_options(&NaiveTime::MIN),
+            TransferOptions {
+                compression: Some(Compression {
+                    algorithm: CompressionAlgorithm::Zstd,
+                    level: Some(3)
+                }),
+                rate_limit: None,
+                buffer_size: Some(String::from("256M")),
+                relay: None,
+                staging_path: Some(String::from("/backups/.staging")),
+                encryption: None,
+            }
+        );
+        assert_eq!(
+            config.jobs[0].targets[0]
+                .transfer_options(&NaiveTime::from_hms_opt(12, 0, 0).unwrap())
+                .rate_limit,
+            Some(String::from("1M"))
+        );
+        assert!(config.jobs[0].targets[1].catch_up);
+        assert_eq!(config.jobs[0].targets[1].progress_interval, 60);
+        assert_eq!(config.jobs[0].targets[0].retries, 3);
+        assert_eq!(
+            config.jobs[0].targets[0].retry_delay(3),
+            std::time::Duration::from_secs(40)
+        );
+        assert_eq!(config.jobs[0].targets[1].retries, 0);
+        assert_eq!(
+            config.jobs[0].targets[1].retry_delay(1),
+            std::time::Duration::from_secs(30)
+        );
+        assert!(config.validate().is_ok());
+
+        let config: Configuration = serde_json::from_str(&JOB.replace(
+            r#""backup_device""#,
+            r#""bandwidth_limits": [{ "rate": "fast" }], "backup_device""#,
+        ))
+        .unwrap();
+
+        assert!(config.validate().is_err());
+
+        let config: Configuration = serde_json::from_str(&JOB.replace(
+            r#""backup_device""#,
+            r#""compression": { "algorithm": "xz", "level": 40 }, "backup_device""#,
+        ))
+        .unwrap();
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn validate_duplicate_target_names() {
+        let config: Configuration = serde_json::from_str(&JOB.replace(
+            r#""config_ssh": { "host": "backup", "config": "/root/.ssh/config" },"#,
+            r#""config_ssh": { "host": "backup" }, "targets": [{ "config_ssh": { "host": "backup" }, "backup_device": "/dev/sdb", "backup_subvolume_path": "/", "backup_path": "/", "policy": [] }],"#,
+        ))
+        .unwrap();
+
+        assert_eq!(config.jobs[0].targets.len(), 2);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn read_file_target() {
+        let config: Configuration = serde_json::from_str(
+            r#"{
+                "source_subvolume_path": "/home",
+                "snapshot_device": "/dev/mapper/data",
+                "snapshot_subvolume_path": "/snapshots",
+                "snapshot_path": "/snapshots",
+                "snapshot_suffix": "home",
+                "user_local": "root",
+                "policy_local": [{ "hours": 1 }],
+                "targets": [
+                    {
+                        "type": "file",
+                        "config_ssh": { "host": "nas" },
+                        "backup_path": "/volume1/backups/laptop",
+                        "policy": { "daily": 7 },
+                        "compression": { "algorithm": "zstd" },
+                        "encryption": { "tool": "age", "recipients": ["age1key"] },
+                        "max_chain_length": 7
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let target = &config.jobs[0].targets[0];
+
+        assert_eq!(target.kind, TargetKind::File);
+        assert_eq!(target.max_chain_length, 7);
+        assert!(target
+            .transfer_options(&NaiveTime::MIN)
+            .encryption
+            .is_some());
+        assert!(config.validate().is_ok());
+
+        // btrfs targets require a device and a subvolume
+        let config: Configuration =
+            serde_json::from_str(&JOB.replace(r#""backup_device": "/dev/mapper/backup","#, ""))
+                .unwrap();
+
+        assert!(config.validate().is_err());
+    }
 }
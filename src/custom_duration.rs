@@ -1,15 +1,22 @@
-use chrono::Duration;
+use chrono::{DateTime, Duration, Months, Utc};
 use serde::Deserialize;
 use std::convert::TryFrom;
+use std::fmt;
 
 use crate::backup_error::BackupError;
 
+/// # CustomDuration
+///
+/// Duration composed of the given units.
+/// Months and years are calendar-aware and can therefore only be converted relative to a point in time (see `CustomDuration::duration_before`).
 #[derive(Debug, Deserialize, Clone)]
 pub struct CustomDuration {
     minutes: Option<i64>,
     hours: Option<i64>,
     days: Option<i64>,
     weeks: Option<i64>,
+    months: Option<u32>,
+    years: Option<u32>,
 }
 
 impl CustomDuration {
@@ -19,6 +26,8 @@ impl CustomDuration {
             hours: None,
             days: None,
             weeks: None,
+            months: None,
+            years: None,
         }
     }
 
@@ -28,6 +37,8 @@ impl CustomDuration {
             hours: Some(hours),
             days: None,
             weeks: None,
+            months: None,
+            years: None,
         }
     }
 
@@ -37,6 +48,8 @@ impl CustomDuration {
             hours: None,
             days: Some(days),
             weeks: None,
+            months: None,
+            years: None,
         }
     }
 
@@ -46,8 +59,81 @@ impl CustomDuration {
             hours: None,
             days: None,
             weeks: Some(weeks),
+            months: None,
+            years: None,
         }
     }
+
+    pub fn months(months: u32) -> Self {
+        CustomDuration {
+            minutes: None,
+            hours: None,
+            days: None,
+            weeks: None,
+            months: Some(months),
+            years: None,
+        }
+    }
+
+    pub fn years(years: u32) -> Self {
+        CustomDuration {
+            minutes: None,
+            hours: None,
+            days: None,
+            weeks: None,
+            months: None,
+            years: Some(years),
+        }
+    }
+
+    /// Convert the duration into a fixed duration ending at the given timestamp
+    ///
+    /// Months and years are subtracted from the timestamp in calendar terms (e.g. one month before the 31st of March is the 28th or 29th of February).
+    ///
+    /// * `timestamp` - end of the duration
+    pub fn duration_before(&self, timestamp: &DateTime<Utc>) -> Result<Duration, BackupError> {
+        let months = self
+            .years
+            .unwrap_or(0)
+            .checked_mul(12)
+            .and_then(|months| months.checked_add(self.months.unwrap_or(0)))
+            .ok_or(BackupError::DurationConversionError)?;
+        let start = timestamp
+            .checked_sub_months(Months::new(months))
+            .ok_or(BackupError::DurationConversionError)?;
+        let fixed = CustomDuration {
+            months: None,
+            years: None,
+            ..self.clone()
+        };
+
+        (*timestamp - start)
+            .checked_add(&Duration::try_from(&fixed)?)
+            .ok_or(BackupError::DurationConversionError)
+    }
+}
+
+impl fmt::Display for CustomDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = [
+            (self.years.map(i64::from), "year"),
+            (self.months.map(i64::from), "month"),
+            (self.weeks, "week"),
+            (self.days, "day"),
+            (self.hours, "hour"),
+            (self.minutes, "minute"),
+        ]
+        .iter()
+        .filter_map(|(value, unit)| {
+            value.map(|value| match value {
+                1 => format!("{} {}", value, unit),
+                _ => format!("{} {}s", value, unit),
+            })
+        })
+        .collect();
+
+        write!(f, "{}", parts.join(" "))
+    }
 }
 
 impl TryFrom<&CustomDuration> for Duration {
@@ -80,6 +166,11 @@ impl TryFrom<&CustomDuration> for Duration {
                 .ok_or(BackupError::DurationConversionError)?
         }
 
+        // months and years do not have a fixed length
+        if cd.months.is_some() || cd.years.is_some() {
+            return Err(BackupError::DurationConversionError);
+        }
+
         Ok(dur)
     }
 }
@@ -89,7 +180,8 @@ mod tests {
     use std::convert::TryInto;
 
     use crate::custom_duration::CustomDuration;
-    use chrono::Duration;
+    use chrono::{Duration, TimeZone, Utc};
+    use std::convert::TryFrom;
 
     #[test]
     fn convert_duration_1() {
@@ -114,4 +206,48 @@ mod tests {
         let test: Duration = (&CustomDuration::weeks(5)).try_into().unwrap();
         assert_eq!(Duration::weeks(5), test);
     }
+
+    #[test]
+    fn convert_duration_months() {
+        assert!(Duration::try_from(&CustomDuration::months(1)).is_err());
+    }
+
+    #[test]
+    fn duration_before_overflow() {
+        let timestamp = Utc.with_ymd_and_hms(2021, 3, 31, 12, 0, 0).unwrap();
+
+        assert!(CustomDuration::years(u32::MAX)
+            .duration_before(&timestamp)
+            .is_err());
+    }
+
+    #[test]
+    fn duration_before() {
+        let timestamp = Utc.with_ymd_and_hms(2021, 3, 31, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            CustomDuration::months(1)
+                .duration_before(&timestamp)
+                .unwrap(),
+            Duration::days(31)
+        );
+        assert_eq!(
+            CustomDuration::years(1)
+                .duration_before(&timestamp)
+                .unwrap(),
+            Duration::days(365)
+        );
+        assert_eq!(
+            CustomDuration::hours(2)
+                .duration_before(&timestamp)
+                .unwrap(),
+            Duration::hours(2)
+        );
+    }
+
+    #[test]
+    fn display_duration() {
+        assert_eq!(CustomDuration::minutes(1).to_string(), "1 minute");
+        assert_eq!(CustomDuration::weeks(5).to_string(), "5 weeks");
+    }
 }
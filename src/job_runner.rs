@@ -0,0 +1,954 @@
+use crate::actions::Actions;
+use crate::backup_error::BackupError;
+use crate::configuration::{JobConfiguration, TargetConfiguration, TargetKind};
+use crate::logging::Scope;
+use crate::objects::*;
+use chrono::{DateTime, Local, Utc};
+use exec_rs::Context;
+use log::{error, info, warn};
+use std::path::Path;
+use uuid::Uuid;
+
+/// # JobRunner
+///
+/// Runs the steps of a backup job (snapshot, send, prune) either together or individually.
+/// The steps concerning the targets are executed for every target of the job; a failure on one target does not prevent the steps on the other targets.
+///
+/// * `actions` - actions used to execute the steps
+/// * `job` - configuration of the job
+/// * `override_guards` - ignore the `min_keep` and `max_delete_per_run` guards of the retention policies
+/// * `lock_owner` - if set, the targets are locked by this owner while operating on them
+pub struct JobRunner<'a> {
+    actions: &'a mut dyn Actions,
+    job: &'a JobConfiguration,
+    override_guards: bool,
+    lock_owner: Option<LockOwner>,
+    relay_program: Option<String>,
+    transfers: Vec<TransferRecord>,
+    created_snapshot: Option<String>,
+    deleted_snapshots: Vec<String>,
+    steps: Vec<StepOutcome>,
+    context_local: Context,
+}
+
+impl<'a> JobRunner<'a> {
+    pub fn new(
+        actions: &'a mut dyn Actions,
+        job: &'a JobConfiguration,
+        override_guards: bool,
+    ) -> Self {
+        JobRunner {
+            actions,
+            job,
+            override_guards,
+            lock_owner: None,
+            relay_program: None,
+            transfers: Vec::new(),
+            created_snapshot: None,
+            deleted_snapshots: Vec::new(),
+            steps: Vec::new(),
+            context_local: job.context_local(),
+        }
+    }
+
+    /// Lock the targets while operating on them
+    ///
+    /// * `owner` - owner of the locks (i.e. the current process)
+    pub fn lock_targets(&mut self, owner: LockOwner) {
+        self.lock_owner = Some(owner);
+    }
+
+    /// Relay the send streams through a program reporting the progress and the statistics of the transfers
+    ///
+    /// * `program` - path of the relay program (see `Relay`)
+    pub fn relay_transfers(&mut self, program: String) {
+        self.relay_program = Some(program);
+    }
+
+    /// Transfers made so far
+    pub fn transfers(&self) -> &[TransferRecord] {
+        &self.transfers
+    }
+
+    /// Path of the snapshot created so far
+    pub fn created_snapshot(&self) -> Option<&str> {
+        self.created_snapshot.as_deref()
+    }
+
+    /// Paths of the snapshots deleted so far
+    pub fn deleted_snapshots(&self) -> &[String] {
+        &self.deleted_snapshots
+    }
+
+    /// Outcomes of the steps executed so far
+    pub fn steps(&self) -> &[StepOutcome] {
+        &self.steps
+    }
+
+    /// Targets of the job
+    pub fn targets(&self) -> &'a [TargetConfiguration] {
+        &self.job.targets
+    }
+
+    /// Run all steps of the job: create a snapshot, send it to all targets, and police the local and target snapshots
+    ///
+    /// The snapshots of a target are only policed, if the new snapshot was confirmed on the target.
+    /// The local snapshots are only policed, if the common parents of all targets could be determined.
+    pub fn run(&mut self) -> Result<(), BackupError> {
+        let scope = Scope::enter(&[("step", "snapshot")]);
+        let result = self.snapshot();
+        let snapshot = self.record_step("snapshot", None, result)?;
+
+        drop(scope);
+
+        let targets: Vec<&'a TargetConfiguration> = self.targets().iter().collect();
+        let (sent, mut failed) = self.for_each_target("send", &targets, |runner, target| {
+            runner.transfer(&snapshot, target)
+        });
+        let (common_parents, common_parents_complete) = self.known_common_parents();
+
+        // any local snapshot could be the parent of the next transfer to a target without a known common parent
+        match common_parents_complete {
+            true => {
+                let _scope = Scope::enter(&[("step", "prune")]);
+                let result = self.prune_local(&snapshot, &common_parents);
+
+                self.record_step("prune", None, result)?
+            }
+            false => {
+                warn!("skipping policing of local snapshots");
+                self.skip_step("prune", None, "common parents of the targets unknown");
+            }
+        }
+
+        for &target in targets
+            .iter()
+            .filter(|t| failed.contains(&t.name().to_string()))
+        {
+            self.skip_step("prune", Some(target), "snapshot not sent");
+        }
+
+        let (_, prune_failed) = self.for_each_target("prune", &sent, |runner, target| {
+            runner.prune_target(&snapshot, &common_parents, target)
+        });
+
+        failed.extend(prune_failed);
+
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(BackupError::TargetsFailed(failed.join(", "))),
+        }
+    }
+
+    /// Create a new local snapshot
+    pub fn snapshot(&mut self) -> Result<SubvolumeInfo, BackupError> {
+        let snapshot = self.actions.create_snapshot(
+            &self.job.source_subvolume_path,
+            &self.job.snapshot_path,
+            &self.job.snapshot_suffix,
+            &self.context_local,
+        )?;
+
+        info!(snapshot = snapshot.fs_path.as_str(), uuid:% = snapshot.uuid; "created new snapshot: \"{}\"", snapshot.fs_path);
+        self.created_snapshot = Some(snapshot.fs_path.clone());
+
+        Ok(snapshot)
+    }
+
+    /// Get the newest local snapshot
+    pub fn latest_snapshot(&mut self) -> Result<SubvolumeInfo, BackupError> {
+        let (devices, mount_information) = self.local_filesystem()?;
+
+        self.actions
+            .get_latest_snapshot(
+                &self.job.snapshot_subvolume_path,
+                &self.job.snapshot_suffix,
+                &devices,
+                &mount_information,
+                &self.context_local,
+            )?
+            .ok_or_else(|| BackupError::NoSnapshot(self.job.snapshot_subvolume_path.clone()))
+    }
+
+    /// Send a snapshot to all targets
+    ///
+    /// * `snapshot` - snapshot to be sent
+    pub fn send(&mut self, snapshot: &SubvolumeInfo) -> Result<(), BackupError> {
+        let targets: Vec<&'a TargetConfiguration> = self.targets().iter().collect();
+        let (_, failed) = self.for_each_target("send", &targets, |runner, target| {
+            runner.transfer(snapshot, target)
+        });
+
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(BackupError::TargetsFailed(failed.join(", "))),
+        }
+    }
+
+    /// Apply the retention policies
+    ///
+    /// The snapshots of a target are only policed, if the snapshot was confirmed on the target.
+    /// The local snapshots are only policed, if the common parents of all targets could be determined.
+    ///
+    /// * `snapshot` - latest local snapshot (will not be deleted)
+    /// * `local` - police the local snapshots
+    /// * `targets` - police the snapshots of the targets
+    pub fn prune(
+        &mut self,
+        snapshot: &SubvolumeInfo,
+        local: bool,
+        targets: bool,
+    ) -> Result<(), BackupError> {
+        let (common_parents, common_parents_complete) = self.known_common_parents();
+
+        if local {
+            // any local snapshot could be the parent of the next transfer to a target without a known common parent
+            match common_parents_complete {
+                true => self.prune_local(snapshot, &common_parents)?,
+                false => warn!("skipping policing of local snapshots"),
+            }
+        }
+
+        if !targets {
+            return Ok(());
+        }
+
+        let targets: Vec<&'a TargetConfiguration> = self.targets().iter().collect();
+        let (_, failed) = self.for_each_target("prune", &targets, |runner, target| {
+            // never police snapshots, if the newest snapshot is not available on the target
+            runner.confirm_snapshot(snapshot, target)?;
+            runner.prune_target(snapshot, &common_parents, target)
+        });
+
+        match failed.is_empty() {
+            true => Ok(()),
+            false => Err(BackupError::TargetsFailed(failed.join(", "))),
+        }
+    }
+
+    /// Send a snapshot to a target and confirm its reception
+    ///
+    /// Incomplete snapshots left behind by interrupted transfers are deleted before and after a failed transfer.
+    ///
+    /// * `snapshot` - snapshot to be sent
+    /// * `target` - target to send the snapshot to
+    fn transfer(
+        &mut self,
+        snapshot: &SubvolumeInfo,
+        target: &TargetConfiguration,
+    ) -> Result<(), BackupError> {
+        // remove the remains of previously interrupted transfers
+        self.delete_incomplete_snapshots(target)?;
+
+        retry_transient(target, || {
+            let res = self.send_to_target(snapshot, target);
+
+            // a failed transfer can leave an incomplete subvolume on the target
+            if res.is_err() {
+                if let Err(cleanup_error) = self.delete_incomplete_snapshots(target) {
+                    warn!("could not delete incomplete snapshots: {}", cleanup_error);
+                }
+            }
+
+            res
+        })?;
+
+        self.confirm_snapshot(snapshot, target)
+    }
+
+    /// Send a snapshot to a target
+    ///
+    /// The statistics of the transfers are recorded (see `transfers`).
+    ///
+    /// * `snapshot` - snapshot to be sent
+    /// * `target` - target to send the snapshot to
+    pub fn send_to_target(
+        &mut self,
+        snapshot: &SubvolumeInfo,
+        target: &TargetConfiguration,
+    ) -> Result<(), BackupError> {
+        let (devices, mount_information) = self.local_filesystem()?;
+        let mut options = target.transfer_options(&Local::now().time());
+
+        options.relay = self.relay_program.as_ref().map(|program| Relay {
+            program: program.clone(),
+            interval: target.progress_interval,
+            bandwidth_limits: target.bandwidth_limits.clone(),
+        });
+
+        // the relay applies the limit applying at the current time of day throughout the transfer
+        if options.relay.is_some() {
+            options.rate_limit = None;
+        }
+
+        let transfers = match target.kind {
+            TargetKind::Btrfs => self.actions.send_snapshot(
+                &self.job.source_subvolume_path,
+                &devices,
+                &self.job.snapshot_subvolume_path,
+                &mount_information,
+                snapshot,
+                &self.context_local,
+                &target.backup_subvolume_path,
+                &target.backup_path,
+                &self.job.context_target(target),
+                &self.job.snapshot_suffix,
+                target.catch_up,
+                &options,
+            )?,
+            TargetKind::File => self.actions.send_snapshot_to_file(
+                &self.job.source_subvolume_path,
+                &devices,
+                &self.job.snapshot_subvolume_path,
+                &mount_information,
+                snapshot,
+                &self.context_local,
+                &target.backup_path,
+                &self.job.context_target(target),
+                target.max_chain_length,
+                &options,
+            )?,
+        };
+
+        self.transfers.extend(
+            transfers
+                .into_iter()
+                .map(|(snapshot, stats)| TransferRecord {
+                    target: target.name().to_string(),
+                    snapshot,
+                    stats,
+                }),
+        );
+
+        Ok(())
+    }
+
+    /// Delete snapshots on a target, which were not received completely
+    ///
+    /// * `target` - target to delete the snapshots from
+    pub fn delete_incomplete_snapshots(
+        &mut self,
+        target: &TargetConfiguration,
+    ) -> Result<Vec<String>, BackupError> {
+        if let TargetKind::File = target.kind {
+            self.actions
+                .delete_incomplete_streams(&target.backup_path, &self.job.context_target(target))?;
+            return Ok(Vec::new());
+        }
+
+        let (devices, mount_information) = self.target_filesystem(target)?;
+
+        self.actions.delete_incomplete_snapshots(
+            &target.backup_subvolume_path,
+            &self.job.snapshot_suffix,
+            &devices,
+            &mount_information,
+            &self.job.context_target(target),
+        )
+    }
+
+    /// Ensure that a snapshot was received completely by a target
+    ///
+    /// * `snapshot` - snapshot that was sent
+    /// * `target` - target the snapshot was sent to
+    pub fn confirm_snapshot(
+        &mut self,
+        snapshot: &SubvolumeInfo,
+        target: &TargetConfiguration,
+    ) -> Result<(), BackupError> {
+        let context = self.job.context_target(target);
+        let confirmed = match target.kind {
+            TargetKind::Btrfs => {
+                self.actions
+                    .confirm_snapshot(&target.backup_subvolume_path, snapshot, &context)?
+            }
+            TargetKind::File => {
+                self.actions
+                    .confirm_stream(&target.backup_path, snapshot, &context)?
+            }
+        };
+
+        match confirmed {
+            true => Ok(()),
+            false => Err(BackupError::SnapshotNotConfirmed(
+                snapshot.btrfs_path.clone(),
+            )),
+        }
+    }
+
+    /// Apply the local retention policy
+    ///
+    /// * `latest_snapshot` - latest local snapshot (will not be deleted)
+    /// * `common_parents` - uuids of the common parents of the local and target snapshots (will not be deleted)
+    pub fn prune_local(
+        &mut self,
+        latest_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+    ) -> Result<(), BackupError> {
+        info!("policing local snapshots");
+
+        let (devices, mount_information) = self.local_filesystem()?;
+        let deleted = self.actions.police_snapshots(
+            &self.job.snapshot_subvolume_path,
+            &self.context_local,
+            latest_snapshot,
+            common_parents,
+            &self.job.policy_local,
+            &Utc::now().into(),
+            &self.job.snapshot_suffix,
+            &devices,
+            &mount_information,
+            self.override_guards,
+        )?;
+
+        self.deleted_snapshots.extend(deleted);
+
+        Ok(())
+    }
+
+    /// Apply the retention policy of a target
+    ///
+    /// * `latest_snapshot` - latest local snapshot (its copy on the target will not be deleted)
+    /// * `common_parents` - uuids of the common parents of the local and target snapshots (will not be deleted)
+    /// * `target` - target to police the snapshots of
+    pub fn prune_target(
+        &mut self,
+        latest_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        target: &TargetConfiguration,
+    ) -> Result<(), BackupError> {
+        info!("policing snapshots of target \"{}\"", target.name());
+
+        let deleted = match target.kind {
+            TargetKind::Btrfs => {
+                let (devices, mount_information) = self.target_filesystem(target)?;
+
+                self.actions.police_snapshots(
+                    &target.backup_subvolume_path,
+                    &self.job.context_target(target),
+                    latest_snapshot,
+                    common_parents,
+                    &target.policy,
+                    &Utc::now().into(),
+                    &self.job.snapshot_suffix,
+                    &devices,
+                    &mount_information,
+                    self.override_guards,
+                )?
+            }
+            TargetKind::File => self.actions.police_streams(
+                &target.backup_path,
+                &self.context_local,
+                &self.job.context_target(target),
+                latest_snapshot,
+                common_parents,
+                &target.policy,
+                &Utc::now().into(),
+                &self.job.snapshot_suffix,
+                self.override_guards,
+            )?,
+        };
+
+        self.deleted_snapshots.extend(
+            deleted
+                .into_iter()
+                .map(|path| format!("{}:{}", target.name(), path)),
+        );
+
+        Ok(())
+    }
+
+    /// Preview the decisions of the local retention policy without deleting any snapshots
+    ///
+    /// * `latest_snapshot` - latest local snapshot (will not be deleted)
+    /// * `common_parents` - uuids of the common parents of the local and target snapshots (will not be deleted)
+    pub fn preview_prune_local(
+        &mut self,
+        latest_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+    ) -> Result<Vec<RetentionDecision>, BackupError> {
+        self.actions.plan_retention(
+            &self.job.snapshot_subvolume_path,
+            &self.context_local,
+            latest_snapshot,
+            common_parents,
+            &self.job.policy_local,
+            &Utc::now().into(),
+            &self.job.snapshot_suffix,
+            self.override_guards,
+        )
+    }
+
+    /// Preview the decisions of the retention policy of a target without deleting any snapshots
+    ///
+    /// * `latest_snapshot` - latest local snapshot (its copy on the target will not be deleted)
+    /// * `common_parents` - uuids of the common parents of the local and target snapshots (will not be deleted)
+    /// * `target` - target to preview the decisions for
+    pub fn preview_prune_target(
+        &mut self,
+        latest_snapshot: &SubvolumeInfo,
+        common_parents: &[Uuid],
+        target: &TargetConfiguration,
+    ) -> Result<Vec<RetentionDecision>, BackupError> {
+        match target.kind {
+            TargetKind::Btrfs => self.actions.plan_retention(
+                &target.backup_subvolume_path,
+                &self.job.context_target(target),
+                latest_snapshot,
+                common_parents,
+                &target.policy,
+                &Utc::now().into(),
+                &self.job.snapshot_suffix,
+                self.override_guards,
+            ),
+            TargetKind::File => self.actions.plan_stream_retention(
+                &target.backup_path,
+                &self.job.context_target(target),
+                latest_snapshot,
+                common_parents,
+                &target.policy,
+                &Utc::now().into(),
+                &self.job.snapshot_suffix,
+                self.override_guards,
+            ),
+        }
+    }
+
+    /// Get the uuids of the newest snapshots shared by the local host and each of the targets
+    ///
+    /// The snapshots serve as parents for incremental transfers and must not be deleted.
+    pub fn common_parents(&mut self) -> Result<Vec<Uuid>, BackupError> {
+        let mut common_parents = Vec::new();
+
+        for target in self.targets() {
+            if let Some(uuid) = self.common_parent(target)? {
+                common_parents.push(uuid);
+            }
+        }
+
+        Ok(common_parents)
+    }
+
+    /// Get the uuids of the common parents of the targets, which can be reached
+    ///
+    /// Returns the uuids and `true`, if the common parents of all targets could be determined.
+    fn known_common_parents(&mut self) -> (Vec<Uuid>, bool) {
+        let mut common_parents = Vec::new();
+        let mut complete = true;
+
+        for target in self.targets() {
+            match self.common_parent(target) {
+                Ok(uuid) => common_parents.extend(uuid),
+                Err(e) => {
+                    warn!(
+                        "could not find the common parent of target \"{}\": {}",
+                        target.name(),
+                        e
+                    );
+                    complete = false;
+                }
+            }
+        }
+
+        (common_parents, complete)
+    }
+
+    /// Get the uuid of the newest snapshot shared by the local host and a target
+    ///
+    /// * `target` - target to find the common parent for
+    pub fn common_parent(
+        &mut self,
+        target: &TargetConfiguration,
+    ) -> Result<Option<Uuid>, BackupError> {
+        let common_parent = match target.kind {
+            TargetKind::Btrfs => self.actions.find_common_parent(
+                &self.job.source_subvolume_path,
+                &self.job.snapshot_subvolume_path,
+                &self.context_local,
+                &target.backup_subvolume_path,
+                &self.job.context_target(target),
+            )?,
+            TargetKind::File => self.actions.find_stream_common_parent(
+                &self.job.source_subvolume_path,
+                &self.job.snapshot_subvolume_path,
+                &self.context_local,
+                &target.backup_path,
+                &self.job.context_target(target),
+            )?,
+        };
+
+        if let Some(sv) = &common_parent {
+            info!(
+                "protecting common parent snapshot \"{}\" of target \"{}\"",
+                sv.btrfs_path,
+                target.name()
+            );
+        }
+
+        Ok(common_parent.map(|sv| sv.uuid))
+    }
+
+    /// Get the local snapshots of the job ordered from oldest to newest
+    pub fn local_snapshots(&mut self) -> Result<Vec<(DateTime<Utc>, Subvolume)>, BackupError> {
+        self.actions.get_snapshots(
+            &self.job.snapshot_subvolume_path,
+            &self.job.snapshot_suffix,
+            &self.context_local,
+        )
+    }
+
+    /// Get the snapshots of the job on a target ordered from oldest to newest
+    ///
+    /// * `target` - target to get the snapshots from
+    pub fn target_snapshots(
+        &mut self,
+        target: &TargetConfiguration,
+    ) -> Result<Vec<(DateTime<Utc>, Subvolume)>, BackupError> {
+        match target.kind {
+            TargetKind::Btrfs => self.actions.get_snapshots(
+                &target.backup_subvolume_path,
+                &self.job.snapshot_suffix,
+                &self.job.context_target(target),
+            ),
+            TargetKind::File => self.actions.get_stream_snapshots(
+                &target.backup_path,
+                &self.job.snapshot_suffix,
+                &self.job.context_target(target),
+            ),
+        }
+    }
+
+    /// Restore a snapshot from a target into a local directory
+    ///
+    /// The target is locked during the restore, if a lock owner is set.
+    /// Returns the local path of the restored snapshot.
+    ///
+    /// * `target` - target to restore the snapshot from
+    /// * `snapshot_name` - name of the snapshot to be restored
+    /// * `destination` - local directory to receive the snapshot in
+    /// * `writable_path` - if set, a writable snapshot of the restored snapshot is created at this path
+    /// * `identity` - identity file for decrypting the streams of file targets (age only)
+    pub fn restore(
+        &mut self,
+        target: &TargetConfiguration,
+        snapshot_name: &str,
+        destination: &str,
+        writable_path: Option<&str>,
+        identity: Option<&str>,
+    ) -> Result<String, BackupError> {
+        let restored = self.with_target_lock(target, |runner| match target.kind {
+            TargetKind::Btrfs => {
+                let (devices, mount_information) = runner.target_filesystem(target)?;
+
+                runner.actions.restore_snapshot(
+                    &runner.job.snapshot_subvolume_path,
+                    &runner.context_local,
+                    &target.backup_subvolume_path,
+                    &devices,
+                    &mount_information,
+                    &runner.job.context_target(target),
+                    snapshot_name,
+                    &runner.job.snapshot_suffix,
+                    destination,
+                )
+            }
+            TargetKind::File => runner.actions.restore_stream(
+                &runner.job.snapshot_subvolume_path,
+                &runner.context_local,
+                &target.backup_path,
+                &runner.job.context_target(target),
+                snapshot_name,
+                destination,
+                identity,
+            ),
+        })?;
+
+        info!("restored snapshot: \"{}\"", restored);
+
+        if let Some(writable_path) = writable_path {
+            self.actions
+                .make_writable(&restored, writable_path, &self.context_local)?;
+        }
+
+        Ok(restored)
+    }
+
+    /// Copy a file or directory out of the newest snapshot taken at or before the given time
+    ///
+    /// The target is locked during the copy, if a lock owner is set.
+    /// Returns the path of the copied file or directory in the snapshot.
+    ///
+    /// * `target` - target to copy from (the local snapshots are used, if not set)
+    /// * `path` - path of the file or directory in the source subvolume (either relative to it or absolute)
+    /// * `before` - time before which the snapshot was taken
+    /// * `destination` - local path to copy to
+    pub fn restore_path(
+        &mut self,
+        target: Option<&TargetConfiguration>,
+        path: &str,
+        before: &DateTime<Utc>,
+        destination: &str,
+    ) -> Result<String, BackupError> {
+        let path = Path::new(path)
+            .strip_prefix(&self.job.source_subvolume_path)
+            .map_or(path.to_string(), |p| p.to_string_lossy().to_string());
+
+        match target {
+            None => {
+                let (devices, mount_information) = self.local_filesystem()?;
+
+                self.actions.restore_path(
+                    &self.job.snapshot_subvolume_path,
+                    &self.job.snapshot_suffix,
+                    &devices,
+                    &mount_information,
+                    &self.context_local,
+                    &path,
+                    before,
+                    destination,
+                    &self.context_local,
+                )
+            }
+            Some(target) => {
+                if let TargetKind::File = target.kind {
+                    return Err(BackupError::UnsupportedForFileTarget(String::from(
+                        "copying files out of snapshots",
+                    )));
+                }
+
+                self.with_target_lock(target, |runner| {
+                    let (devices, mount_information) = runner.target_filesystem(target)?;
+
+                    runner.actions.restore_path(
+                        &target.backup_subvolume_path,
+                        &runner.job.snapshot_suffix,
+                        &devices,
+                        &mount_information,
+                        &runner.job.context_target(target),
+                        &path,
+                        before,
+                        destination,
+                        &runner.context_local,
+                    )
+                })
+            }
+        }
+    }
+
+    /// Get the local snapshots of the job matched with the snapshots on a target ordered from oldest to newest
+    ///
+    /// * `target` - target to match the local snapshots with
+    pub fn inventory(
+        &mut self,
+        target: &TargetConfiguration,
+    ) -> Result<Vec<InventoryEntry>, BackupError> {
+        let local = self.local_snapshots()?;
+        let remote = self.target_snapshots(target)?;
+        let common_parent = self.common_parent(target)?;
+
+        Ok(InventoryEntry::inventory(
+            local,
+            remote,
+            common_parent.as_ref(),
+        ))
+    }
+
+    /// Execute a step for each of the given targets
+    ///
+    /// If a lock owner is set, every target is locked while the step is executed.
+    /// Returns the targets, for which the step succeeded, and the names of the targets, for which it failed.
+    ///
+    /// * `name` - name of the step (used to record its outcome)
+    /// * `targets` - targets to execute the step for
+    /// * `step` - step to be executed
+    fn for_each_target<F>(
+        &mut self,
+        name: &str,
+        targets: &[&'a TargetConfiguration],
+        mut step: F,
+    ) -> (Vec<&'a TargetConfiguration>, Vec<String>)
+    where
+        F: FnMut(&mut Self, &'a TargetConfiguration) -> Result<(), BackupError>,
+    {
+        let mut succeeded = Vec::new();
+        let mut failed = Vec::new();
+
+        for &target in targets {
+            let _scope = Scope::enter(&[("step", name), ("target", target.name())]);
+            let result = self.with_target_lock(target, |runner| step(runner, target));
+
+            match self.record_step(name, Some(target), result) {
+                Ok(()) => succeeded.push(target),
+                Err(e) => {
+                    error!(error_kind = e.kind(); "target \"{}\" failed: {}", target.name(), e);
+                    failed.push(target.name().to_string());
+                }
+            }
+        }
+
+        (succeeded, failed)
+    }
+
+    /// Record the outcome of a step
+    ///
+    /// * `name` - name of the step
+    /// * `target` - target the step was executed for (`None` for steps on the local host)
+    /// * `result` - result of the step (returned unchanged)
+    fn record_step<T>(
+        &mut self,
+        name: &str,
+        target: Option<&TargetConfiguration>,
+        result: Result<T, BackupError>,
+    ) -> Result<T, BackupError> {
+        self.steps.push(StepOutcome {
+            step: name.to_string(),
+            target: target.map(|t| t.name().to_string()),
+            outcome: match result {
+                Ok(_) => Outcome::Succeeded,
+                Err(_) => Outcome::Failed,
+            },
+            message: result.as_ref().err().map(|e| e.to_string()),
+        });
+
+        result
+    }
+
+    fn skip_step(&mut self, name: &str, target: Option<&TargetConfiguration>, reason: &str) {
+        self.steps.push(StepOutcome {
+            step: name.to_string(),
+            target: target.map(|t| t.name().to_string()),
+            outcome: Outcome::Skipped,
+            message: Some(reason.to_string()),
+        });
+    }
+
+    fn with_target_lock<T, F>(
+        &mut self,
+        target: &TargetConfiguration,
+        step: F,
+    ) -> Result<T, BackupError>
+    where
+        F: FnOnce(&mut Self) -> Result<T, BackupError>,
+    {
+        let owner = match self.lock_owner.clone() {
+            Some(owner) => owner,
+            None => return step(self),
+        };
+        let lock_path = format!(
+            "{}/.backup-btrfs-{}.lock",
+            target.backup_path.trim_end_matches('/'),
+            self.job.name()
+        );
+        let context = self.job.context_target(target);
+
+        self.actions.acquire_lock(&lock_path, &owner, &context)?;
+
+        let result = step(self);
+
+        if let Err(e) = self.actions.release_lock(&lock_path, &owner, &context) {
+            warn!("could not release lock \"{}\": {}", lock_path, e);
+        }
+
+        result
+    }
+
+    fn local_filesystem(&mut self) -> Result<(Vec<String>, Vec<MountInformation>), BackupError> {
+        Ok((
+            self.actions
+                .read_link(&self.job.snapshot_device, &self.context_local)?,
+            self.actions.get_mount_information(&self.context_local)?,
+        ))
+    }
+
+    fn target_filesystem(
+        &mut self,
+        target: &TargetConfiguration,
+    ) -> Result<(Vec<String>, Vec<MountInformation>), BackupError> {
+        let context = self.job.context_target(target);
+
+        Ok((
+            self.actions.read_link(&target.backup_device, &context)?,
+            self.actions.get_mount_information(&context)?,
+        ))
+    }
+}
+
+/// Execute a transfer and retry it after transient errors (see `BackupError::is_transient`) with exponential backoff
+///
+/// Other errors are returned immediately, as retrying cannot resolve them.
+///
+/// * `target` - target of the transfer, which configures the retries
+/// * `transfer` - transfer to be executed
+fn retry_transient<F>(target: &TargetConfiguration, mut transfer: F) -> Result<(), BackupError>
+where
+    F: FnMut() -> Result<(), BackupError>,
+{
+    let mut retry = 0;
+
+    while let Err(e) = transfer() {
+        if !e.is_transient() || retry >= target.retries {
+            return Err(e);
+        }
+
+        retry += 1;
+
+        let delay = target.retry_delay(retry);
+
+        warn!(
+            "transfer to target \"{}\" failed ({}); retry {} of {} in {} s",
+            target.name(),
+            e,
+            retry,
+            target.retries,
+            delay.as_secs()
+        );
+        std::thread::sleep(delay);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    fn target() -> TargetConfiguration {
+        serde_json::from_str(
+            r#"{ "backup_path": "/backups", "policy": [{ "days": 1 }], "retries": 3, "retry_delay": 0 }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn retry_transient_errors() {
+        let mut attempts = 0;
+
+        let res = retry_transient(&target(), || {
+            attempts += 1;
+            match attempts {
+                3 => Ok(()),
+                _ => Err(BackupError::IoError(ErrorKind::BrokenPipe.into())),
+            }
+        });
+
+        assert!(res.is_ok());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn retry_non_transient_errors() {
+        let mut attempts = 0;
+
+        let res = retry_transient(&target(), || {
+            attempts += 1;
+            Err(BackupError::ProgramNotAvailable {
+                program: String::from("zstd"),
+                location: String::from("remote (nas)"),
+            })
+        });
+
+        assert!(matches!(res, Err(BackupError::ProgramNotAvailable { .. })));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -3,4 +3,11 @@ pub mod backup_error;
 pub mod commands;
 pub mod configuration;
 pub mod custom_duration;
+pub mod job_runner;
+pub mod lock;
+pub mod logging;
+pub mod metrics;
 pub mod objects;
+pub mod relay;
+pub mod retention;
+pub mod state;
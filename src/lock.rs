@@ -0,0 +1,113 @@
+use crate::backup_error::BackupError;
+use crate::objects::LockOwner;
+use log::warn;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// # LocalLock
+///
+/// Lock of a job on the local host held as an exclusive `flock` on the file `<state directory>/<job name>.lock`, which contains the owner of the lock.
+/// The lock is released, when the value is dropped or the process terminates.
+///
+/// * `path` - path of the lock file
+/// * `file` - open lock file holding the `flock`
+#[derive(Debug)]
+pub struct LocalLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl LocalLock {
+    /// Acquire the lock of a job
+    ///
+    /// The kernel releases the lock of a process, which is no longer running, so stale locks do not need to be removed.
+    /// The lock file itself is never deleted, as another process could be waiting for the lock on it.
+    ///
+    /// * `state_directory` - directory containing the lock files
+    /// * `job_name` - name of the job
+    /// * `owner` - owner of the lock (i.e. the current process)
+    pub fn acquire(
+        state_directory: &str,
+        job_name: &str,
+        owner: &LockOwner,
+    ) -> Result<Self, BackupError> {
+        let path = Path::new(state_directory).join(format!("{}.lock", job_name));
+
+        fs::create_dir_all(state_directory)?;
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {
+                file.set_len(0)?;
+                file.write_all(owner.to_string().as_bytes())?;
+
+                Ok(LocalLock { path, file })
+            }
+            Err(TryLockError::WouldBlock) => {
+                let holder = fs::read_to_string(&path)?.trim().to_string();
+
+                Err(BackupError::Locked {
+                    lock: path.display().to_string(),
+                    owner: match holder.is_empty() {
+                        true => String::from("an unknown process"),
+                        false => holder,
+                    },
+                })
+            }
+            Err(TryLockError::Error(e)) => Err(e.into()),
+        }
+    }
+}
+
+impl Drop for LocalLock {
+    fn drop(&mut self) {
+        // the lock itself is released when the file is closed
+        if let Err(e) = self.file.set_len(0) {
+            warn!("could not clear lock \"{}\": {}", self.path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acquire_local_lock() {
+        let dir = std::env::temp_dir().join("backup-btrfs-test-acquire-local-lock");
+        let state_directory = dir.to_str().unwrap();
+        let owner = LockOwner {
+            pid: std::process::id(),
+            host: "host".into(),
+        };
+
+        let lock = LocalLock::acquire(state_directory, "test", &owner).unwrap();
+
+        assert!(matches!(
+            LocalLock::acquire(state_directory, "test", &owner),
+            Err(BackupError::Locked { .. })
+        ));
+
+        drop(lock);
+
+        // a lock file left behind without a lock is taken over
+        fs::write(dir.join("test.lock"), "0@host").unwrap();
+
+        let lock = LocalLock::acquire(state_directory, "test", &owner).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("test.lock")).unwrap(),
+            owner.to_string()
+        );
+
+        drop(lock);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
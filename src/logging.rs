@@ -0,0 +1,184 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use env_logger::Env;
+use log::kv::{self, Key, Value, VisitSource};
+use log::Record;
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+use std::cell::RefCell;
+use std::io::Write;
+
+/// # LogFormat
+///
+/// * `Text` - human readable lines (the format of `env_logger`)
+/// * `Json` - one JSON object per line with the fields `timestamp`, `level`, `module`, and `message`, the fields of the current scope (`job`, `step`, `target`), and the fields of the log record (e.g. `snapshot`, `uuid`, `duration`, `error_kind`)
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+thread_local! {
+    static SCOPE: RefCell<Vec<(&'static str, String)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// # Scope
+///
+/// Fields added to all JSON log lines of the current thread until the scope is dropped.
+///
+/// * `len` - number of fields of the enclosing scopes
+pub struct Scope {
+    len: usize,
+}
+
+impl Scope {
+    /// Enter a scope
+    ///
+    /// * `fields` - names and values of the fields (e.g. `[("job", "home")]`)
+    pub fn enter(fields: &[(&'static str, &str)]) -> Self {
+        SCOPE.with(|scope| {
+            let mut scope = scope.borrow_mut();
+            let len = scope.len();
+
+            scope.extend(fields.iter().map(|(key, value)| (*key, value.to_string())));
+
+            Scope { len }
+        })
+    }
+}
+
+impl Drop for Scope {
+    fn drop(&mut self) {
+        SCOPE.with(|scope| scope.borrow_mut().truncate(self.len));
+    }
+}
+
+struct JsonFields<'a>(&'a mut Map<String, JsonValue>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(v) = value.to_bool() {
+            JsonValue::from(v)
+        } else if let Some(v) = value.to_u64() {
+            JsonValue::from(v)
+        } else if let Some(v) = value.to_i64() {
+            JsonValue::from(v)
+        } else if let Some(v) = value.to_f64() {
+            JsonValue::from(v)
+        } else {
+            JsonValue::from(value.to_string())
+        };
+
+        self.0.insert(key.as_str().to_string(), value);
+
+        Ok(())
+    }
+}
+
+/// Format a log record as a JSON object
+///
+/// The fields of the record take precedence over the fields of the scope.
+///
+/// * `record` - log record to be formatted
+/// * `timestamp` - time of the record
+fn format_json(record: &Record, timestamp: DateTime<Utc>) -> String {
+    let mut fields = Map::new();
+
+    fields.insert(
+        String::from("timestamp"),
+        timestamp
+            .to_rfc3339_opts(SecondsFormat::Millis, true)
+            .into(),
+    );
+    fields.insert(
+        String::from("level"),
+        record.level().as_str().to_lowercase().into(),
+    );
+    fields.insert(String::from("module"), record.target().into());
+    fields.insert(String::from("message"), record.args().to_string().into());
+
+    SCOPE.with(|scope| {
+        for (key, value) in scope.borrow().iter() {
+            fields.insert(key.to_string(), value.as_str().into());
+        }
+    });
+
+    let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+
+    JsonValue::Object(fields).to_string()
+}
+
+/// Initialize the logger
+///
+/// The level is taken from `RUST_LOG` and defaults to the given filter.
+///
+/// * `format` - format of the log lines
+/// * `default_filter` - filter used, if `RUST_LOG` is not set
+pub fn init(format: LogFormat, default_filter: &str) {
+    let mut builder =
+        env_logger::Builder::from_env(Env::default().default_filter_or(default_filter));
+
+    if format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", format_json(record, Utc::now())));
+    }
+
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use log::Level;
+
+    #[test]
+    fn format_json_fields() {
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
+        let _job = Scope::enter(&[("job", "home")]);
+        let line = {
+            let _step = Scope::enter(&[("step", "send"), ("target", "nas")]);
+            let kvs: [(&str, Value); 2] = [
+                (
+                    "snapshot",
+                    Value::from("/snapshots/2020-05-10T12:00:00Z_home"),
+                ),
+                ("duration", Value::from(1.5)),
+            ];
+
+            format_json(
+                &Record::builder()
+                    .args(format_args!("sent snapshot"))
+                    .level(Level::Info)
+                    .target("backup_btrfs::actions")
+                    .key_values(&kvs)
+                    .build(),
+                timestamp,
+            )
+        };
+        let json: JsonValue = serde_json::from_str(&line).unwrap();
+
+        assert_eq!(json["timestamp"], "2020-05-10T12:00:00.000Z");
+        assert_eq!(json["level"], "info");
+        assert_eq!(json["module"], "backup_btrfs::actions");
+        assert_eq!(json["message"], "sent snapshot");
+        assert_eq!(json["job"], "home");
+        assert_eq!(json["step"], "send");
+        assert_eq!(json["target"], "nas");
+        assert_eq!(json["snapshot"], "/snapshots/2020-05-10T12:00:00Z_home");
+        assert_eq!(json["duration"], 1.5);
+
+        // the fields of a scope are removed when it is dropped
+        let json: JsonValue = serde_json::from_str(&format_json(
+            &Record::builder()
+                .args(format_args!("done"))
+                .level(Level::Info)
+                .build(),
+            timestamp,
+        ))
+        .unwrap();
+
+        assert_eq!(json["job"], "home");
+        assert!(json.get("step").is_none());
+    }
+}
@@ -0,0 +1,261 @@
+use crate::backup_error::BackupError;
+use crate::state::JobState;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+/// # Metric
+///
+/// A metric in the Prometheus text format.
+///
+/// * `name` - name of the metric
+/// * `help` - description of the metric
+/// * `kind` - type of the metric (`gauge` or `counter`)
+/// * `samples` - labels and value of every sample
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Metric {
+    fn new(name: &'static str, help: &'static str, kind: &'static str) -> Self {
+        Metric {
+            name,
+            help,
+            kind,
+            samples: Vec::new(),
+        }
+    }
+
+    fn render(&self, output: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+
+        let _ = writeln!(output, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(output, "# TYPE {} {}", self.name, self.kind);
+
+        for (labels, value) in &self.samples {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(name, value)| format!("{}=\"{}\"", name, escape_label(value)))
+                .collect();
+
+            let _ = writeln!(output, "{}{{{}}} {}", self.name, labels.join(","), value);
+        }
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Render the metrics of the jobs in the Prometheus text format
+///
+/// * `states` - names and persisted states of the jobs
+pub fn render(states: &[(&str, JobState)]) -> String {
+    let mut last_success = Metric::new(
+        "backup_btrfs_last_success_timestamp_seconds",
+        "Start time of the last successful run.",
+        "gauge",
+    );
+    let mut last_run = Metric::new(
+        "backup_btrfs_last_run_timestamp_seconds",
+        "Start time of the last run.",
+        "gauge",
+    );
+    let mut last_run_successful = Metric::new(
+        "backup_btrfs_last_run_successful",
+        "Whether the last run completed successfully.",
+        "gauge",
+    );
+    let mut last_run_duration = Metric::new(
+        "backup_btrfs_last_run_duration_seconds",
+        "Duration of the last run.",
+        "gauge",
+    );
+    let mut bytes_sent = Metric::new(
+        "backup_btrfs_last_run_sent_bytes",
+        "Bytes sent to a target by the last run.",
+        "gauge",
+    );
+    let mut deleted = Metric::new(
+        "backup_btrfs_last_run_deleted_snapshots",
+        "Snapshots deleted by the last run.",
+        "gauge",
+    );
+    let mut local_snapshots = Metric::new(
+        "backup_btrfs_local_snapshots",
+        "Local snapshots after the last run.",
+        "gauge",
+    );
+    let mut target_snapshots = Metric::new(
+        "backup_btrfs_target_snapshots",
+        "Snapshots on a target after the last run.",
+        "gauge",
+    );
+    let mut failed_runs = Metric::new(
+        "backup_btrfs_failed_runs_total",
+        "Runs that failed.",
+        "counter",
+    );
+    let mut target_failures = Metric::new(
+        "backup_btrfs_target_failures_total",
+        "Runs in which a step failed for a target.",
+        "counter",
+    );
+
+    for (job, state) in states {
+        let job_labels = || vec![("job", job.to_string())];
+        let target_labels =
+            |target: &str| vec![("job", job.to_string()), ("target", target.to_string())];
+
+        if let Some(ts) = state.last_success {
+            last_success
+                .samples
+                .push((job_labels(), ts.timestamp() as f64));
+        }
+
+        if let Some(ts) = state.last_run {
+            last_run.samples.push((job_labels(), ts.timestamp() as f64));
+            last_run_successful.samples.push((
+                job_labels(),
+                match state.last_run_successful {
+                    true => 1.0,
+                    false => 0.0,
+                },
+            ));
+            deleted
+                .samples
+                .push((job_labels(), state.last_deleted_snapshots.len() as f64));
+
+            if let Some(end) = state.last_run_end {
+                last_run_duration
+                    .samples
+                    .push((job_labels(), (end - ts).num_milliseconds() as f64 / 1000.0));
+            }
+        }
+
+        let mut sent: BTreeMap<&str, u64> = BTreeMap::new();
+
+        for transfer in &state.last_transfers {
+            *sent.entry(&transfer.target).or_default() += transfer.stats.bytes;
+        }
+
+        for (target, bytes) in sent {
+            bytes_sent
+                .samples
+                .push((target_labels(target), bytes as f64));
+        }
+
+        if let Some(count) = state.local_snapshot_count {
+            local_snapshots.samples.push((job_labels(), count as f64));
+        }
+
+        for (target, count) in &state.target_snapshot_counts {
+            target_snapshots
+                .samples
+                .push((target_labels(target), *count as f64));
+        }
+
+        failed_runs
+            .samples
+            .push((job_labels(), state.failed_runs as f64));
+
+        for (target, count) in &state.target_failures {
+            target_failures
+                .samples
+                .push((target_labels(target), *count as f64));
+        }
+    }
+
+    let mut output = String::new();
+
+    for metric in [
+        last_success,
+        last_run,
+        last_run_successful,
+        last_run_duration,
+        bytes_sent,
+        deleted,
+        local_snapshots,
+        target_snapshots,
+        failed_runs,
+        target_failures,
+    ] {
+        metric.render(&mut output);
+    }
+
+    output
+}
+
+/// Write the metrics to a file read by the textfile collector of the node exporter
+///
+/// The metrics are written to a temporary file first, which is then renamed, so that the collector never reads an incomplete file.
+///
+/// * `path` - path of the file (the extension must be `.prom`)
+/// * `metrics` - metrics in the Prometheus text format
+pub fn write(path: &str, metrics: &str) -> Result<(), BackupError> {
+    let path = Path::new(path);
+    let temp_path = path.with_extension("prom.tmp");
+
+    fs::write(&temp_path, metrics)?;
+    fs::rename(&temp_path, path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{TransferRecord, TransferStats};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn render_metrics() {
+        let state = JobState {
+            last_run: Some(Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap()),
+            last_run_end: Some(Utc.with_ymd_and_hms(2020, 5, 10, 12, 1, 30).unwrap()),
+            last_run_successful: true,
+            last_success: Some(Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap()),
+            last_transfers: vec![TransferRecord {
+                target: String::from("nas"),
+                snapshot: String::from("/snapshots/2020-05-10T12:00:00Z_home"),
+                stats: TransferStats {
+                    bytes: 2048,
+                    seconds: 2.0,
+                },
+            }],
+            local_snapshot_count: Some(12),
+            target_snapshot_counts: BTreeMap::from([(String::from("nas"), 30)]),
+            failed_runs: 2,
+            target_failures: BTreeMap::from([(String::from("nas"), 1)]),
+            ..JobState::default()
+        };
+        let metrics = render(&[("home", state)]);
+
+        assert!(metrics.contains(
+            "# TYPE backup_btrfs_last_success_timestamp_seconds gauge\nbackup_btrfs_last_success_timestamp_seconds{job=\"home\"} 1589112000\n"
+        ));
+        assert!(metrics.contains("backup_btrfs_last_run_duration_seconds{job=\"home\"} 90\n"));
+        assert!(metrics
+            .contains("backup_btrfs_last_run_sent_bytes{job=\"home\",target=\"nas\"} 2048\n"));
+        assert!(metrics.contains("backup_btrfs_local_snapshots{job=\"home\"} 12\n"));
+        assert!(metrics.contains("backup_btrfs_target_snapshots{job=\"home\",target=\"nas\"} 30\n"));
+        assert!(metrics.contains("# TYPE backup_btrfs_failed_runs_total counter\n"));
+        assert!(
+            metrics.contains("backup_btrfs_target_failures_total{job=\"home\",target=\"nas\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
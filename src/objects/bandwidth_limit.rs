@@ -0,0 +1,115 @@
+use chrono::NaiveTime;
+use serde::{Deserialize, Serialize};
+
+/// # BandwidthLimit
+///
+/// Limit of the transfer rate to a target, which can be restricted to a time of day.
+///
+/// * `rate` - maximum rate in the syntax of `mbuffer` (e.g. "10M" for 10 MiB/s)
+/// * `from` - local time at which the limit starts to apply (defaults to midnight)
+/// * `until` - local time at which the limit stops to apply (defaults to midnight); windows ending before they start span midnight
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BandwidthLimit {
+    pub rate: String,
+    #[serde(default)]
+    pub from: Option<NaiveTime>,
+    #[serde(default)]
+    pub until: Option<NaiveTime>,
+}
+
+impl BandwidthLimit {
+    /// Check whether the limit applies at the given time of day
+    ///
+    /// * `time` - local time of day
+    pub fn applies_at(&self, time: &NaiveTime) -> bool {
+        let from = self.from.unwrap_or(NaiveTime::MIN);
+
+        match self.until {
+            Some(until) if from < until => from <= *time && *time < until,
+            Some(until) if until < from => from <= *time || *time < until,
+            _ => from <= *time,
+        }
+    }
+
+    /// Maximum rate in bytes per second; `None`, if the rate is invalid or zero
+    ///
+    /// The rate is a number with an optional binary suffix (`k`, `M`, `G`, or `T`).
+    pub fn bytes_per_second(&self) -> Option<u64> {
+        let rate = self.rate.trim();
+        let (number, factor) = match rate.chars().last() {
+            Some('k' | 'K') => (&rate[..rate.len() - 1], 1u64 << 10),
+            Some('m' | 'M') => (&rate[..rate.len() - 1], 1 << 20),
+            Some('g' | 'G') => (&rate[..rate.len() - 1], 1 << 30),
+            Some('t' | 'T') => (&rate[..rate.len() - 1], 1 << 40),
+            _ => (rate, 1),
+        };
+
+        number
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite() && *number > 0.0)
+            .map(|number| (number * factor as f64) as u64)
+            .filter(|rate| *rate > 0)
+    }
+}
+
+/// Rate of the first limit applying at the given time of day in bytes per second
+///
+/// * `limits` - bandwidth limits
+/// * `time` - local time of day
+pub fn current_rate(limits: &[BandwidthLimit], time: &NaiveTime) -> Option<u64> {
+    limits
+        .iter()
+        .find(|limit| limit.applies_at(time))
+        .and_then(BandwidthLimit::bytes_per_second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_at() {
+        let always: BandwidthLimit = serde_json::from_str(r#"{ "rate": "10M" }"#).unwrap();
+        let office: BandwidthLimit =
+            serde_json::from_str(r#"{ "rate": "1M", "from": "08:00", "until": "18:00" }"#).unwrap();
+        let night: BandwidthLimit =
+            serde_json::from_str(r#"{ "rate": "1M", "from": "22:00", "until": "06:00" }"#).unwrap();
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+        assert!(always.applies_at(&time(0, 0)));
+        assert!(always.applies_at(&time(23, 59)));
+        assert!(office.applies_at(&time(8, 0)));
+        assert!(office.applies_at(&time(17, 59)));
+        assert!(!office.applies_at(&time(18, 0)));
+        assert!(!office.applies_at(&time(7, 59)));
+        assert!(night.applies_at(&time(23, 0)));
+        assert!(night.applies_at(&time(5, 0)));
+        assert!(!night.applies_at(&time(12, 0)));
+        assert_eq!(
+            current_rate(&[office.clone(), always.clone()], &time(12, 0)),
+            Some(1 << 20)
+        );
+        assert_eq!(
+            current_rate(&[office, always], &time(20, 0)),
+            Some(10 << 20)
+        );
+        assert_eq!(current_rate(&[night], &time(12, 0)), None);
+    }
+
+    #[test]
+    fn bytes_per_second() {
+        let limit = |rate: &str| BandwidthLimit {
+            rate: rate.into(),
+            from: None,
+            until: None,
+        };
+
+        assert_eq!(limit("512").bytes_per_second(), Some(512));
+        assert_eq!(limit("10k").bytes_per_second(), Some(10 << 10));
+        assert_eq!(limit("1.5M").bytes_per_second(), Some(3 << 19));
+        assert_eq!(limit("2G").bytes_per_second(), Some(2 << 30));
+        assert_eq!(limit("0M").bytes_per_second(), None);
+        assert_eq!(limit("fast").bytes_per_second(), None);
+    }
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+/// # Compression
+///
+/// Compression of the send stream between the local and the remote host.
+///
+/// * `algorithm` - compression algorithm
+/// * `level` - compression level (defaults to the default level of the compressor)
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct Compression {
+    pub algorithm: CompressionAlgorithm,
+    #[serde(default)]
+    pub level: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    Zstd,
+    Lz4,
+    Xz,
+}
+
+impl CompressionAlgorithm {
+    /// Name of the compressor program
+    pub fn program(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "zstd",
+            CompressionAlgorithm::Lz4 => "lz4",
+            CompressionAlgorithm::Xz => "xz",
+        }
+    }
+
+    /// Extension of compressed files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Zstd => "zst",
+            CompressionAlgorithm::Lz4 => "lz4",
+            CompressionAlgorithm::Xz => "xz",
+        }
+    }
+
+    /// Arguments for decompressing stdin to stdout
+    pub fn decompress_args(&self) -> Vec<String> {
+        vec![String::from("-d"), String::from("-c")]
+    }
+
+    /// Range of the compression levels accepted by the compressor
+    pub fn levels(&self) -> std::ops::RangeInclusive<u32> {
+        match self {
+            CompressionAlgorithm::Zstd => 1..=19,
+            CompressionAlgorithm::Lz4 => 1..=12,
+            CompressionAlgorithm::Xz => 0..=9,
+        }
+    }
+}
+
+impl Compression {
+    /// Name of the compressor program
+    pub fn program(&self) -> &'static str {
+        self.algorithm.program()
+    }
+
+    /// Arguments for compressing stdin to stdout
+    pub fn compress_args(&self) -> Vec<String> {
+        let mut args = vec![String::from("-c")];
+
+        if let Some(level) = self.level {
+            args.push(format!("-{}", level));
+        }
+
+        args
+    }
+
+    /// Arguments for decompressing stdin to stdout
+    pub fn decompress_args(&self) -> Vec<String> {
+        self.algorithm.decompress_args()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_compression() {
+        let compression: Compression =
+            serde_json::from_str(r#"{ "algorithm": "zstd", "level": 3 }"#).unwrap();
+
+        assert_eq!(compression.program(), "zstd");
+        assert_eq!(compression.compress_args(), vec!["-c", "-3"]);
+        assert_eq!(compression.decompress_args(), vec!["-d", "-c"]);
+        assert!(serde_json::from_str::<Compression>(r#"{ "algorithm": "gzip" }"#).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+use serde::{Deserialize, Serialize};
+
+/// # Encryption
+///
+/// Encryption of the send streams stored on a file target.
+///
+/// * `tool` - encryption tool
+/// * `recipients` - recipients the streams are encrypted for (age public keys or gpg key ids)
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub struct Encryption {
+    pub tool: EncryptionTool,
+    pub recipients: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EncryptionTool {
+    Age,
+    Gpg,
+}
+
+impl EncryptionTool {
+    /// Name of the encryption program
+    pub fn program(&self) -> &'static str {
+        match self {
+            EncryptionTool::Age => "age",
+            EncryptionTool::Gpg => "gpg",
+        }
+    }
+
+    /// Arguments for decrypting stdin to stdout
+    ///
+    /// * `identity` - identity file (required by age; gpg uses its keyring)
+    pub fn decrypt_args(&self, identity: Option<&str>) -> Vec<String> {
+        let mut args = match self {
+            EncryptionTool::Age => vec![String::from("-d")],
+            EncryptionTool::Gpg => vec![String::from("--batch"), String::from("--decrypt")],
+        };
+
+        if let (EncryptionTool::Age, Some(identity)) = (self, identity) {
+            args.push(String::from("-i"));
+            args.push(identity.to_string());
+        }
+
+        args
+    }
+
+    /// Extension of encrypted files
+    pub fn extension(&self) -> &'static str {
+        match self {
+            EncryptionTool::Age => "age",
+            EncryptionTool::Gpg => "gpg",
+        }
+    }
+}
+
+impl Encryption {
+    /// Arguments for encrypting stdin to stdout
+    pub fn encrypt_args(&self) -> Vec<String> {
+        let (mut args, recipient_flag) = match self.tool {
+            EncryptionTool::Age => (vec![], "-r"),
+            EncryptionTool::Gpg => (
+                vec![
+                    String::from("--batch"),
+                    String::from("--trust-model"),
+                    String::from("always"),
+                    String::from("--encrypt"),
+                ],
+                "--recipient",
+            ),
+        };
+
+        for recipient in &self.recipients {
+            args.push(recipient_flag.to_string());
+            args.push(recipient.clone());
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encrypt_args() {
+        let age: Encryption =
+            serde_json::from_str(r#"{ "tool": "age", "recipients": ["age1a", "age1b"] }"#).unwrap();
+        let gpg: Encryption =
+            serde_json::from_str(r#"{ "tool": "gpg", "recipients": ["backup@example.com"] }"#)
+                .unwrap();
+
+        assert_eq!(age.tool.program(), "age");
+        assert_eq!(
+            age.tool.decrypt_args(Some("/root/key.txt")),
+            vec!["-d", "-i", "/root/key.txt"]
+        );
+        assert_eq!(
+            gpg.tool.decrypt_args(Some("/root/key.txt")),
+            vec!["--batch", "--decrypt"]
+        );
+        assert_eq!(age.encrypt_args(), vec!["-r", "age1a", "-r", "age1b"]);
+        assert_eq!(
+            gpg.encrypt_args(),
+            vec![
+                "--batch",
+                "--trust-model",
+                "always",
+                "--encrypt",
+                "--recipient",
+                "backup@example.com"
+            ]
+        );
+        assert!(
+            serde_json::from_str::<Encryption>(r#"{ "tool": "openssl", "recipients": [] }"#)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use super::Subvolume;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// # InventoryEntry
+///
+/// A snapshot of a job as found on the local host and on a target.
+///
+/// * `timestamp` - timestamp parsed from the name of the snapshot
+/// * `local` - local snapshot (`None`, if it only exists on the target)
+/// * `remote` - snapshot on the target (`None`, if it only exists locally)
+/// * `common_parent` - `true`, if the snapshot is the current common parent of the local host and the target
+/// * `on_both_sides` - `true`, if the snapshot exists locally and on the target
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct InventoryEntry {
+    pub timestamp: DateTime<Utc>,
+    pub local: Option<Subvolume>,
+    pub remote: Option<Subvolume>,
+    pub common_parent: bool,
+    pub on_both_sides: bool,
+}
+
+impl InventoryEntry {
+    /// Match the local snapshots with the snapshots on a target
+    ///
+    /// A snapshot on the target matches a local snapshot, if it was received from it (or, for file targets, has its uuid).
+    /// The entries are ordered from oldest to newest.
+    ///
+    /// * `local` - local snapshots together with their timestamps
+    /// * `remote` - snapshots on the target together with their timestamps
+    /// * `common_parent` - uuid of the current common parent
+    pub fn inventory(
+        local: Vec<(DateTime<Utc>, Subvolume)>,
+        remote: Vec<(DateTime<Utc>, Subvolume)>,
+        common_parent: Option<&Uuid>,
+    ) -> Vec<InventoryEntry> {
+        let mut remote: Vec<Option<(DateTime<Utc>, Subvolume)>> =
+            remote.into_iter().map(Some).collect();
+        let mut entries: Vec<InventoryEntry> = local
+            .into_iter()
+            .map(|(timestamp, sv)| {
+                let copy = remote
+                    .iter_mut()
+                    .find(|r| {
+                        r.as_ref().is_some_and(|(_, r)| {
+                            r.received_uuid == Some(sv.uuid) || r.uuid == sv.uuid
+                        })
+                    })
+                    .and_then(Option::take)
+                    .map(|(_, r)| r);
+
+                InventoryEntry {
+                    timestamp,
+                    common_parent: common_parent == Some(&sv.uuid),
+                    on_both_sides: copy.is_some(),
+                    local: Some(sv),
+                    remote: copy,
+                }
+            })
+            .collect();
+
+        entries.extend(
+            remote
+                .into_iter()
+                .flatten()
+                .map(|(timestamp, sv)| InventoryEntry {
+                    timestamp,
+                    local: None,
+                    remote: Some(sv),
+                    common_parent: false,
+                    on_both_sides: false,
+                }),
+        );
+        entries.sort_by_key(|e| e.timestamp);
+
+        entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn snapshot(day: u32, uuid: u128, received_uuid: Option<u128>) -> (DateTime<Utc>, Subvolume) {
+        (
+            Utc.with_ymd_and_hms(2021, 5, day, 7, 0, 0).unwrap(),
+            Subvolume {
+                btrfs_path: format!("/snapshots/2021-05-0{}T07:00:00Z_test", day),
+                uuid: Uuid::from_u128(uuid),
+                parent_uuid: None,
+                received_uuid: received_uuid.map(Uuid::from_u128),
+            },
+        )
+    }
+
+    #[test]
+    fn inventory() {
+        let entries = InventoryEntry::inventory(
+            vec![snapshot(2, 2, None), snapshot(3, 3, None)],
+            vec![snapshot(1, 11, Some(1)), snapshot(2, 12, Some(2))],
+            Some(&Uuid::from_u128(2)),
+        );
+
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (
+                    e.local.as_ref().map(|sv| sv.uuid.as_u128()),
+                    e.remote.as_ref().map(|sv| sv.uuid.as_u128()),
+                    e.common_parent,
+                    e.on_both_sides
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (None, Some(11), false, false),
+                (Some(2), Some(12), true, true),
+                (Some(3), None, false, false),
+            ]
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use crate::backup_error::BackupError;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// # LockOwner
+///
+/// Process holding a lock; represented as `<pid>@<host>` in lock files.
+///
+/// * `pid` - process id
+/// * `host` - name of the host the process runs on
+#[derive(Debug, PartialEq, Clone)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub host: String,
+}
+
+impl LockOwner {
+    /// Get the owner representing the current process
+    pub fn current() -> Result<Self, BackupError> {
+        Ok(LockOwner {
+            pid: std::process::id(),
+            host: fs::read_to_string("/proc/sys/kernel/hostname")?
+                .trim()
+                .to_string(),
+        })
+    }
+
+    /// Check whether the lock was left behind by a process, which is no longer running
+    ///
+    /// Only locks of processes on the same host can be checked; locks of other hosts are never considered stale.
+    ///
+    /// * `current` - owner representing the current process
+    pub fn is_stale(&self, current: &LockOwner) -> bool {
+        self.host == current.host
+            && self.pid != current.pid
+            && !Path::new(&format!("/proc/{}", self.pid)).exists()
+    }
+}
+
+impl fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.pid, self.host)
+    }
+}
+
+impl FromStr for LockOwner {
+    type Err = BackupError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pid, host) = s
+            .split_once('@')
+            .ok_or_else(|| BackupError::LockOwnerParsing(s.to_string()))?;
+
+        Ok(LockOwner {
+            pid: pid
+                .parse()
+                .map_err(|_| BackupError::LockOwnerParsing(s.to_string()))?,
+            host: host.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_lock_owner() {
+        let owner: LockOwner = "1234@backup.local".parse().unwrap();
+
+        assert_eq!(
+            owner,
+            LockOwner {
+                pid: 1234,
+                host: "backup.local".into()
+            }
+        );
+        assert_eq!(owner.to_string(), "1234@backup.local");
+        assert!("backup.local".parse::<LockOwner>().is_err());
+        assert!("abc@backup.local".parse::<LockOwner>().is_err());
+    }
+
+    #[test]
+    fn stale_lock_owner() {
+        let current = LockOwner {
+            pid: std::process::id(),
+            host: "host".into(),
+        };
+
+        // pid 0 never appears in /proc
+        assert!(LockOwner {
+            pid: 0,
+            host: "host".into()
+        }
+        .is_stale(&current));
+        assert!(!LockOwner {
+            pid: 0,
+            host: "other".into()
+        }
+        .is_stale(&current));
+        assert!(!current.is_stale(&current));
+    }
+}
@@ -1,7 +1,29 @@
+mod bandwidth_limit;
+mod compression;
+mod encryption;
+mod inventory_entry;
+mod lock_owner;
 mod mount_information;
+mod notification;
+mod retention_decision;
+mod step_outcome;
+mod stream_manifest;
 mod subvolume;
 mod subvolume_info;
+mod transfer_options;
+mod transfer_stats;
 
+pub use bandwidth_limit::*;
+pub use compression::*;
+pub use encryption::*;
+pub use inventory_entry::*;
+pub use lock_owner::*;
 pub use mount_information::*;
+pub use notification::*;
+pub use retention_decision::*;
+pub use step_outcome::*;
+pub use stream_manifest::*;
 pub use subvolume::*;
 pub use subvolume_info::*;
+pub use transfer_options::*;
+pub use transfer_stats::*;
@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// # Notifier
+///
+/// Backend delivering notifications about the outcome of job runs (`type` in the configuration file).
+///
+/// * `Sendmail` - mail sent with a `sendmail`-compatible command
+///   * `command` - path of the command (defaults to "sendmail")
+///   * `from` - sender of the mail
+///   * `to` - recipients of the mail
+/// * `Webhook` - JSON payload (see `Notification`) posted to a URL with `curl`
+///   * `url` - URL of the webhook
+/// * `NotifySend` - desktop notification shown with `notify-send`
+///   * `user` - user whose desktop session shows the notification (defaults to the user running the job)
+///
+/// Every notifier has the field `events` listing the events it is notified about (defaults to failures only).
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Notifier {
+    Sendmail {
+        #[serde(default = "default_sendmail_command")]
+        command: String,
+        #[serde(default)]
+        from: Option<String>,
+        to: Vec<String>,
+        #[serde(default = "default_events")]
+        events: Vec<NotificationEvent>,
+    },
+    Webhook {
+        url: String,
+        #[serde(default = "default_events")]
+        events: Vec<NotificationEvent>,
+    },
+    NotifySend {
+        #[serde(default)]
+        user: Option<String>,
+        #[serde(default = "default_events")]
+        events: Vec<NotificationEvent>,
+    },
+}
+
+fn default_sendmail_command() -> String {
+    String::from("sendmail")
+}
+
+fn default_events() -> Vec<NotificationEvent> {
+    vec![NotificationEvent::Failure]
+}
+
+impl Notifier {
+    /// Check whether the notifier is notified about an event
+    ///
+    /// A recovery is a success as well, so notifiers of successes are also notified about recoveries.
+    ///
+    /// * `event` - event to be checked
+    pub fn is_notified(&self, event: NotificationEvent) -> bool {
+        let events = match self {
+            Notifier::Sendmail { events, .. }
+            | Notifier::Webhook { events, .. }
+            | Notifier::NotifySend { events, .. } => events,
+        };
+
+        events.contains(&event)
+            || (event == NotificationEvent::Recovery
+                && events.contains(&NotificationEvent::Success))
+    }
+
+    /// Name of the notifier (used in log messages)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notifier::Sendmail { .. } => "sendmail",
+            Notifier::Webhook { .. } => "webhook",
+            Notifier::NotifySend { .. } => "notify-send",
+        }
+    }
+}
+
+/// # NotificationEvent
+///
+/// * `Failure` - a run failed
+/// * `Success` - a run completed successfully
+/// * `Recovery` - a run completed successfully after the previous run failed
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotificationEvent {
+    Failure,
+    Success,
+    Recovery,
+}
+
+/// # Notification
+///
+/// Notification about the outcome of a job run (the payload of webhooks).
+///
+/// * `job` - name of the job
+/// * `event` - event the notification is about
+/// * `timestamp` - start time of the run
+/// * `host` - name of the local host
+/// * `snapshot` - path of the snapshot created by the run
+/// * `errors` - error of the run followed by its causes and the errors of the failed steps
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Notification {
+    pub job: String,
+    pub event: NotificationEvent,
+    pub timestamp: DateTime<Utc>,
+    pub host: String,
+    pub snapshot: Option<String>,
+    pub errors: Vec<String>,
+}
+
+impl Notification {
+    /// Summary of the notification (e.g. the subject of mails)
+    pub fn summary(&self) -> String {
+        format!(
+            "backup-btrfs on {}: job \"{}\" {}",
+            self.host,
+            self.job,
+            match self.event {
+                NotificationEvent::Failure => "failed",
+                NotificationEvent::Success => "succeeded",
+                NotificationEvent::Recovery => "recovered",
+            }
+        )
+    }
+
+    /// Body of the notification listing the snapshot and the errors
+    pub fn body(&self) -> String {
+        let mut lines = vec![format!("run started at {}", self.timestamp.to_rfc3339())];
+
+        if let Some(snapshot) = &self.snapshot {
+            lines.push(format!("snapshot: {}", snapshot));
+        }
+
+        if !self.errors.is_empty() {
+            lines.push(String::from("errors:"));
+            lines.extend(self.errors.iter().map(|e| format!("  {}", e)));
+        }
+
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn read_notifiers() {
+        let notifiers: Vec<Notifier> = serde_json::from_str(
+            r#"[
+                { "type": "sendmail", "to": ["admin@example.com"] },
+                { "type": "webhook", "url": "https://example.com/hook", "events": ["failure", "success"] },
+                { "type": "notify_send", "user": "hannes", "events": ["recovery"] }
+            ]"#,
+        )
+        .unwrap();
+
+        assert!(
+            matches!(&notifiers[0], Notifier::Sendmail { command, .. } if command == "sendmail")
+        );
+        assert!(notifiers[0].is_notified(NotificationEvent::Failure));
+        assert!(!notifiers[0].is_notified(NotificationEvent::Recovery));
+        assert!(notifiers[1].is_notified(NotificationEvent::Recovery));
+        assert!(!notifiers[2].is_notified(NotificationEvent::Success));
+    }
+
+    #[test]
+    fn summary_body() {
+        let notification = Notification {
+            job: String::from("home"),
+            event: NotificationEvent::Failure,
+            timestamp: Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap(),
+            host: String::from("laptop"),
+            snapshot: None,
+            errors: vec![
+                String::from("failed targets: nas"),
+                String::from("send to target nas: command error"),
+            ],
+        };
+
+        assert_eq!(
+            notification.summary(),
+            "backup-btrfs on laptop: job \"home\" failed"
+        );
+        assert_eq!(
+            notification.body(),
+            "run started at 2020-05-10T12:00:00+00:00\nerrors:\n  failed targets: nas\n  send to target nas: command error"
+        );
+    }
+}
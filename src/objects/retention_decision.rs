@@ -0,0 +1,21 @@
+use super::Subvolume;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// # RetentionDecision
+///
+/// Outcome of applying a retention policy to a snapshot.
+///
+/// * `timestamp` - timestamp parsed from the name of the snapshot
+/// * `subvolume` - snapshot the decision applies to
+/// * `bucket` - policy entry the age of the snapshot falls into (`None`, if the snapshot is older than all entries)
+/// * `keep` - `true`, if the snapshot is kept; `false`, if it is deleted
+/// * `reason` - reason for the decision
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct RetentionDecision {
+    pub timestamp: DateTime<Utc>,
+    pub subvolume: Subvolume,
+    pub bucket: Option<String>,
+    pub keep: bool,
+    pub reason: String,
+}
@@ -0,0 +1,30 @@
+use serde::{Deserialize, Serialize};
+
+/// # StepOutcome
+///
+/// Outcome of a step of a job run.
+///
+/// * `step` - name of the step (`snapshot`, `send`, or `prune`)
+/// * `target` - name of the target the step was executed for (`None` for steps on the local host)
+/// * `outcome` - outcome of the step
+/// * `message` - error of a failed step or reason for skipping a step
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StepOutcome {
+    pub step: String,
+    pub target: Option<String>,
+    pub outcome: Outcome,
+    pub message: Option<String>,
+}
+
+/// # Outcome
+///
+/// * `Succeeded` - the step completed successfully
+/// * `Failed` - the step failed
+/// * `Skipped` - the step was not executed
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Succeeded,
+    Failed,
+    Skipped,
+}
@@ -0,0 +1,157 @@
+use super::{CompressionAlgorithm, EncryptionTool, Subvolume};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// # StreamManifest
+///
+/// Index of the send streams stored on a file target (`manifest.json` in the directory of the target).
+///
+/// * `streams` - streams ordered from oldest to newest
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StreamManifest {
+    pub streams: Vec<StreamEntry>,
+}
+
+/// # StreamEntry
+///
+/// Send stream of a snapshot stored in a file.
+///
+/// * `snapshot` - btrfs path of the local snapshot the stream was created from
+/// * `uuid` - uuid of the local snapshot the stream was created from
+/// * `parent_uuid` - uuid of the local snapshot the stream is relative to (`None` for a full stream)
+/// * `file` - name of the file in the directory of the target
+/// * `compression` - compression algorithm applied to the stream
+/// * `encryption` - encryption tool applied to the stream (after the compression)
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct StreamEntry {
+    pub snapshot: String,
+    pub uuid: Uuid,
+    pub parent_uuid: Option<Uuid>,
+    pub file: String,
+    pub compression: Option<CompressionAlgorithm>,
+    pub encryption: Option<EncryptionTool>,
+}
+
+impl StreamManifest {
+    /// Name of the manifest file
+    pub const FILE_NAME: &'static str = "manifest.json";
+
+    /// Get the stream of a snapshot
+    ///
+    /// * `uuid` - uuid of the local snapshot
+    pub fn get(&self, uuid: &Uuid) -> Option<&StreamEntry> {
+        self.streams.iter().find(|entry| entry.uuid == *uuid)
+    }
+
+    /// Get the streams required to restore a snapshot starting with the full stream
+    ///
+    /// Returns `None`, if a stream of the chain is missing.
+    ///
+    /// * `uuid` - uuid of the local snapshot
+    pub fn chain(&self, uuid: &Uuid) -> Option<Vec<&StreamEntry>> {
+        let mut chain = vec![self.get(uuid)?];
+
+        while let Some(parent_uuid) = chain[chain.len() - 1].parent_uuid {
+            // a cycle would indicate a corrupt manifest
+            if chain.len() > self.streams.len() {
+                return None;
+            }
+
+            chain.push(self.get(&parent_uuid)?);
+        }
+
+        chain.reverse();
+
+        Some(chain)
+    }
+
+    /// Add a stream replacing a previous stream of the same snapshot
+    ///
+    /// * `entry` - stream to be added
+    pub fn add(&mut self, entry: StreamEntry) {
+        self.streams.retain(|e| e.uuid != entry.uuid);
+        self.streams.push(entry);
+    }
+
+    /// Snapshots stored on the target in the form of subvolumes
+    ///
+    /// The uuid and the received uuid of a subvolume are the uuid of the local snapshot, the btrfs path is the path of the local snapshot.
+    pub fn subvolumes(&self) -> Vec<Subvolume> {
+        self.streams
+            .iter()
+            .map(|entry| Subvolume {
+                btrfs_path: entry.snapshot.clone(),
+                uuid: entry.uuid,
+                parent_uuid: entry.parent_uuid,
+                received_uuid: Some(entry.uuid),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(uuid: u128, parent_uuid: Option<u128>) -> StreamEntry {
+        StreamEntry {
+            snapshot: format!("/snapshots/{}", uuid),
+            uuid: Uuid::from_u128(uuid),
+            parent_uuid: parent_uuid.map(Uuid::from_u128),
+            file: format!("{}.btrfs", uuid),
+            compression: None,
+            encryption: None,
+        }
+    }
+
+    #[test]
+    fn chain() {
+        let manifest = StreamManifest {
+            streams: vec![
+                entry(1, None),
+                entry(2, Some(1)),
+                entry(3, Some(2)),
+                entry(4, None),
+                entry(5, Some(6)),
+            ],
+        };
+
+        assert_eq!(
+            manifest
+                .chain(&Uuid::from_u128(3))
+                .unwrap()
+                .iter()
+                .map(|e| e.uuid)
+                .collect::<Vec<Uuid>>(),
+            vec![Uuid::from_u128(1), Uuid::from_u128(2), Uuid::from_u128(3)]
+        );
+        assert_eq!(manifest.chain(&Uuid::from_u128(4)).unwrap().len(), 1);
+        assert!(manifest.chain(&Uuid::from_u128(5)).is_none());
+        assert!(manifest.chain(&Uuid::from_u128(7)).is_none());
+    }
+
+    #[test]
+    fn read_manifest() {
+        let manifest: StreamManifest = serde_json::from_str(
+            r#"{ "streams": [{
+                "snapshot": "/snapshots/2020-05-10T12:00:00Z_home",
+                "uuid": "00000000-0000-0000-0000-000000000001",
+                "parent_uuid": null,
+                "file": "2020-05-10T12:00:00Z_home.btrfs.zst.age",
+                "compression": "zstd",
+                "encryption": "age"
+            }] }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            manifest.streams[0].compression,
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(manifest.streams[0].encryption, Some(EncryptionTool::Age));
+        assert_eq!(
+            manifest.subvolumes()[0].received_uuid,
+            Some(Uuid::from_u128(1))
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use serde::Serialize;
 use uuid::Uuid;
 
 /// # Subvolume
@@ -8,7 +9,7 @@ use uuid::Uuid;
 /// * `uuid` - btrfs subvolume uuid
 /// * `parent_uuid` - btrfs uuid of the parent of the subvolume
 /// * `received_uuid` - btrfs uuid of the subvolume, which was sent
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Subvolume {
     pub btrfs_path: String,
     pub uuid: Uuid,
@@ -0,0 +1,137 @@
+use super::{BandwidthLimit, Compression, Encryption, SubvolumeInfo};
+use crate::backup_error::BackupError;
+
+/// # TransferOptions
+///
+/// Options for sending snapshots to a target.
+///
+/// * `compression` - compression of the send stream (only applied to remote targets)
+/// * `rate_limit` - maximum transfer rate in the syntax of `mbuffer` (e.g. "10M"); only used without a relay, as it cannot change during the transfer
+/// * `buffer_size` - size of the buffer in the send pipeline in the syntax of `mbuffer` (e.g. "256M")
+/// * `relay` - relay reporting the progress of the transfer
+/// * `staging_path` - directory on the target to stage the send stream in, so that interrupted transfers can be resumed
+/// * `encryption` - encryption of the send stream (only applied to file targets)
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct TransferOptions {
+    pub compression: Option<Compression>,
+    pub rate_limit: Option<String>,
+    pub buffer_size: Option<String>,
+    pub relay: Option<Relay>,
+    pub staging_path: Option<String>,
+    pub encryption: Option<Encryption>,
+}
+
+/// # Relay
+///
+/// Program inserted into the send pipeline after `btrfs send`, which passes the stream through, logs the progress, and writes the size and duration of the transfer to a file (i.e. `<program> relay --interval <interval> --stats-file <path>`).
+/// With bandwidth limits, the program is also inserted before the buffer (i.e. `<program> throttle --bandwidth-limits <limits>`) and limits the transfer rate to the limit applying at the current time of day.
+///
+/// * `program` - path of the relay program (usually the current executable)
+/// * `interval` - interval between progress reports in seconds
+/// * `bandwidth_limits` - limits of the transfer rate
+#[derive(Debug, PartialEq, Clone)]
+pub struct Relay {
+    pub program: String,
+    pub interval: u64,
+    pub bandwidth_limits: Vec<BandwidthLimit>,
+}
+
+impl Relay {
+    /// Arguments of the relay stage
+    ///
+    /// * `stats_file` - path of the file to write the size and duration of the transfer to
+    pub fn args(&self, stats_file: &str) -> Vec<String> {
+        vec![
+            String::from("relay"),
+            String::from("--interval"),
+            self.interval.to_string(),
+            String::from("--stats-file"),
+            stats_file.to_string(),
+        ]
+    }
+
+    /// Arguments of the throttle stage; `None`, if there are no bandwidth limits
+    pub fn throttle_args(&self) -> Result<Option<Vec<String>>, BackupError> {
+        if self.bandwidth_limits.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(vec![
+            String::from("throttle"),
+            String::from("--bandwidth-limits"),
+            serde_json::to_string(&self.bandwidth_limits)?,
+        ]))
+    }
+}
+
+impl TransferOptions {
+    /// Program used for buffering and rate limiting
+    pub const BUFFER_PROGRAM: &'static str = "mbuffer";
+
+    /// Name of the file a send stream is staged in
+    ///
+    /// The name contains the uuids of the snapshot and the parent, as the stream depends on both.
+    ///
+    /// * `snapshot` - snapshot to be sent
+    /// * `parent` - parent of an incremental transfer
+    pub fn staging_file_name(snapshot: &SubvolumeInfo, parent: Option<&SubvolumeInfo>) -> String {
+        match parent {
+            Some(parent) => format!("{}_{}.stream", snapshot.uuid, parent.uuid),
+            None => format!("{}.stream", snapshot.uuid),
+        }
+    }
+
+    /// Arguments of the buffer stage; `None`, if neither a rate limit nor a buffer size is configured
+    pub fn buffer_args(&self) -> Option<Vec<String>> {
+        if self.rate_limit.is_none() && self.buffer_size.is_none() {
+            return None;
+        }
+
+        let mut args = vec![String::from("-q")];
+
+        if let Some(buffer_size) = &self.buffer_size {
+            args.push(String::from("-m"));
+            args.push(buffer_size.clone());
+        }
+
+        if let Some(rate_limit) = &self.rate_limit {
+            args.push(String::from("-r"));
+            args.push(rate_limit.clone());
+        }
+
+        Some(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_args() {
+        assert_eq!(TransferOptions::default().buffer_args(), None);
+        assert_eq!(
+            TransferOptions {
+                rate_limit: Some("10M".into()),
+                ..TransferOptions::default()
+            }
+            .buffer_args(),
+            Some(vec!["-q".into(), "-r".into(), "10M".into()])
+        );
+        assert_eq!(
+            TransferOptions {
+                rate_limit: Some("10M".into()),
+                buffer_size: Some("256M".into()),
+                ..TransferOptions::default()
+            }
+            .buffer_args(),
+            Some(vec![
+                "-q".into(),
+                "-m".into(),
+                "256M".into(),
+                "-r".into(),
+                "10M".into()
+            ])
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// # TransferStats
+///
+/// Size and duration of a transfer.
+///
+/// * `bytes` - number of bytes transferred
+/// * `seconds` - duration of the transfer in seconds
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransferStats {
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+/// # TransferRecord
+///
+/// Transfer of a snapshot to a target.
+///
+/// * `target` - name of the target
+/// * `snapshot` - path of the snapshot on the local host
+/// * `stats` - size and duration of the transfer
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransferRecord {
+    pub target: String,
+    pub snapshot: String,
+    #[serde(flatten)]
+    pub stats: TransferStats,
+}
+
+impl TransferStats {
+    /// Average throughput in bytes per second
+    pub fn throughput(&self) -> f64 {
+        match self.seconds > 0.0 {
+            true => self.bytes as f64 / self.seconds,
+            false => 0.0,
+        }
+    }
+
+    /// Format a number of bytes with a binary unit (e.g. "1.5 GiB")
+    ///
+    /// * `bytes` - number of bytes
+    pub fn format_bytes(bytes: f64) -> String {
+        let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut value = bytes;
+        let mut unit = 0;
+
+        while value >= 1024.0 && unit < units.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        match unit {
+            0 => format!("{} {}", value, units[unit]),
+            _ => format!("{:.1} {}", value, units[unit]),
+        }
+    }
+
+    /// Format a duration as "hh:mm:ss"
+    ///
+    /// * `seconds` - duration in seconds
+    pub fn format_duration(seconds: f64) -> String {
+        let seconds = seconds as u64;
+
+        format!(
+            "{:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        )
+    }
+}
+
+impl Display for TransferStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} in {} ({}/s)",
+            TransferStats::format_bytes(self.bytes as f64),
+            TransferStats::format_duration(self.seconds),
+            TransferStats::format_bytes(self.throughput())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let stats = TransferStats {
+            bytes: 3 * 1024 * 1024 * 1024,
+            seconds: 3725.0,
+        };
+
+        assert_eq!(stats.to_string(), "3.0 GiB in 01:02:05 (844.5 KiB/s)");
+        assert_eq!(TransferStats::format_bytes(512.0), "512 B");
+        assert_eq!(TransferStats::default().throughput(), 0.0);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::objects::{current_rate, BandwidthLimit, TransferStats};
+use chrono::Local;
+use log::info;
+use std::io::{ErrorKind, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Copy a stream and log the progress at the given interval
+///
+/// Returns the number of bytes copied and the duration of the copy.
+///
+/// * `reader` - source of the stream
+/// * `writer` - destination of the stream
+/// * `interval` - interval between progress reports
+pub fn relay<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    interval: Duration,
+) -> std::io::Result<TransferStats> {
+    let start = Instant::now();
+    let mut buffer = vec![0u8; 1 << 20];
+    let mut bytes = 0u64;
+    let mut last_report = start;
+    let mut last_bytes = 0u64;
+
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buffer[..count])?;
+        bytes += count as u64;
+
+        let now = Instant::now();
+
+        if now - last_report >= interval {
+            let current = TransferStats {
+                bytes: bytes - last_bytes,
+                seconds: (now - last_report).as_secs_f64(),
+            };
+
+            info!(
+                "transferred {} in {} (currently {}/s)",
+                TransferStats::format_bytes(bytes as f64),
+                TransferStats::format_duration((now - start).as_secs_f64()),
+                TransferStats::format_bytes(current.throughput())
+            );
+            last_report = now;
+            last_bytes = bytes;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(TransferStats {
+        bytes,
+        seconds: start.elapsed().as_secs_f64(),
+    })
+}
+
+/// Copy a stream and limit its rate to the bandwidth limit applying at the current time of day
+///
+/// The limit is determined again for every chunk, so that a transfer running into or out of the window of a limit changes its rate.
+/// Returns the number of bytes copied.
+///
+/// * `reader` - source of the stream
+/// * `writer` - destination of the stream
+/// * `bandwidth_limits` - limits of the transfer rate
+pub fn throttle<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    bandwidth_limits: &[BandwidthLimit],
+) -> std::io::Result<u64> {
+    let mut buffer = vec![0u8; 1 << 20];
+    let mut bytes = 0u64;
+    // rate in bytes per second, the time since which it applies, and the bytes copied since then
+    let mut rate = None;
+    let mut rate_start = Instant::now();
+    let mut rate_bytes = 0u64;
+
+    loop {
+        let current = current_rate(bandwidth_limits, &Local::now().time());
+
+        if current != rate {
+            match current {
+                Some(current) => info!(
+                    "limiting the transfer rate to {}/s",
+                    TransferStats::format_bytes(current as f64)
+                ),
+                None => info!("no longer limiting the transfer rate"),
+            }
+
+            rate = current;
+            rate_start = Instant::now();
+            rate_bytes = 0;
+        }
+
+        // chunks of a tenth of the rate keep the transfer smooth
+        let len = rate.map_or(buffer.len(), |rate| {
+            (rate as usize / 10).clamp(1 << 12, buffer.len())
+        });
+        let count = match reader.read(&mut buffer[..len]) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buffer[..count])?;
+        bytes += count as u64;
+
+        if let Some(rate) = rate {
+            rate_bytes += count as u64;
+
+            let due = Duration::from_secs_f64(rate_bytes as f64 / rate as f64);
+            let elapsed = rate_start.elapsed();
+
+            if due > elapsed {
+                std::thread::sleep(due - elapsed);
+            }
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relay_stream() {
+        let data: Vec<u8> = (0..3_000_000).map(|idx| (idx % 251) as u8).collect();
+        let mut output = Vec::new();
+
+        let stats = relay(&mut data.as_slice(), &mut output, Duration::ZERO).unwrap();
+
+        assert_eq!(output, data);
+        assert_eq!(stats.bytes, 3_000_000);
+    }
+
+    #[test]
+    fn throttle_stream() {
+        let data: Vec<u8> = (0..600_000).map(|idx| (idx % 251) as u8).collect();
+        let mut output = Vec::new();
+        let limits = [BandwidthLimit {
+            rate: String::from("2M"),
+            from: None,
+            until: None,
+        }];
+        let start = Instant::now();
+
+        let bytes = throttle(&mut data.as_slice(), &mut output, &limits).unwrap();
+
+        assert_eq!(output, data);
+        assert_eq!(bytes, 600_000);
+        // 600000 bytes at 2 MiB/s take about 0.29 s
+        assert!(start.elapsed() >= Duration::from_millis(250));
+    }
+}
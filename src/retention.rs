@@ -0,0 +1,333 @@
+use crate::backup_error::BackupError;
+use crate::custom_duration::CustomDuration;
+use chrono::{DateTime, Duration, Utc};
+use policer::police;
+use serde::Deserialize;
+use std::cmp::Reverse;
+
+/// # RetentionPolicy
+///
+/// A retention policy is either given as a list of durations (e.g. `[{ "hours": 1 }, { "days": 1 }]`), which is evaluated by `policer`, or as counts of snapshots to keep (e.g. `{ "keep_last": 3, "daily": 7, "monthly": 12 }`).
+/// To configure guards for a list of durations, the list can be wrapped in an object (e.g. `{ "durations": [{ "days": 1 }], "min_keep": 3 }`).
+#[derive(Debug, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum RetentionPolicy {
+    Durations(Vec<CustomDuration>),
+    GuardedDurations(RetentionDurations),
+    Counts(RetentionCounts),
+}
+
+/// # RetentionDurations
+///
+/// List of durations evaluated by `policer` together with guards.
+///
+/// * `durations` - durations of the policy
+/// * `min_keep` - minimum number of snapshots to keep regardless of the durations
+/// * `max_delete_per_run` - maximum number of snapshots to delete in a single run
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionDurations {
+    pub durations: Vec<CustomDuration>,
+    #[serde(default)]
+    pub min_keep: Option<usize>,
+    #[serde(default)]
+    pub max_delete_per_run: Option<usize>,
+}
+
+/// # RetentionCounts
+///
+/// Grandfather-father-son retention.
+/// For every period type, the newest snapshot of each of the most recent periods containing snapshots is kept.
+///
+/// * `keep_last` - number of newest snapshots to keep
+/// * `hourly` - number of hours to keep a snapshot for
+/// * `daily` - number of days to keep a snapshot for
+/// * `weekly` - number of (ISO) weeks to keep a snapshot for
+/// * `monthly` - number of months to keep a snapshot for
+/// * `yearly` - number of years to keep a snapshot for
+/// * `min_keep` - minimum number of snapshots to keep regardless of the counts
+/// * `max_delete_per_run` - maximum number of snapshots to delete in a single run
+#[derive(Debug, Default, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionCounts {
+    #[serde(default)]
+    pub keep_last: usize,
+    #[serde(default)]
+    pub hourly: usize,
+    #[serde(default)]
+    pub daily: usize,
+    #[serde(default)]
+    pub weekly: usize,
+    #[serde(default)]
+    pub monthly: usize,
+    #[serde(default)]
+    pub yearly: usize,
+    #[serde(default)]
+    pub min_keep: Option<usize>,
+    #[serde(default)]
+    pub max_delete_per_run: Option<usize>,
+}
+
+/// # RetentionMark
+///
+/// Outcome of applying a retention policy to a single snapshot.
+///
+/// * `keep` - `true`, if the snapshot is retained by the policy
+/// * `bucket` - description of the policy entry the snapshot falls into
+#[derive(Debug, PartialEq, Clone)]
+pub struct RetentionMark {
+    pub keep: bool,
+    pub bucket: Option<String>,
+}
+
+impl RetentionCounts {
+    /// Check whether the counts keep any snapshots (i.e. at least one count is not zero)
+    pub fn keeps_snapshots(&self) -> bool {
+        [
+            self.keep_last,
+            self.hourly,
+            self.daily,
+            self.weekly,
+            self.monthly,
+            self.yearly,
+        ]
+        .iter()
+        .any(|count| *count > 0)
+    }
+}
+
+impl RetentionPolicy {
+    /// Apply the policy to a list of snapshots
+    ///
+    /// Returns a mark for every snapshot in the order of the given list.
+    ///
+    /// * `timestamp` - timestamp to use as the current moment
+    /// * `snapshots` - snapshots together with their timestamps
+    pub fn apply<T>(
+        &self,
+        timestamp: &DateTime<Utc>,
+        snapshots: &[(DateTime<Utc>, T)],
+    ) -> Result<Vec<RetentionMark>, BackupError> {
+        match self {
+            RetentionPolicy::Durations(policy)
+            | RetentionPolicy::GuardedDurations(RetentionDurations {
+                durations: policy, ..
+            }) => RetentionPolicy::apply_durations(policy, timestamp, snapshots),
+            RetentionPolicy::Counts(counts) => Ok(RetentionPolicy::apply_counts(counts, snapshots)),
+        }
+    }
+
+    /// Minimum number of snapshots to keep
+    pub fn min_keep(&self) -> Option<usize> {
+        match self {
+            RetentionPolicy::Durations(_) => None,
+            RetentionPolicy::GuardedDurations(durations) => durations.min_keep,
+            RetentionPolicy::Counts(counts) => counts.min_keep,
+        }
+    }
+
+    /// Maximum number of snapshots to delete in a single run
+    pub fn max_delete_per_run(&self) -> Option<usize> {
+        match self {
+            RetentionPolicy::Durations(_) => None,
+            RetentionPolicy::GuardedDurations(durations) => durations.max_delete_per_run,
+            RetentionPolicy::Counts(counts) => counts.max_delete_per_run,
+        }
+    }
+
+    fn apply_durations<T>(
+        policy: &[CustomDuration],
+        timestamp: &DateTime<Utc>,
+        snapshots: &[(DateTime<Utc>, T)],
+    ) -> Result<Vec<RetentionMark>, BackupError> {
+        let durations = policy
+            .iter()
+            .map(|d| d.duration_before(timestamp))
+            .collect::<Result<Vec<Duration>, BackupError>>()?;
+        let indexed: Vec<(DateTime<Utc>, usize)> = snapshots
+            .iter()
+            .enumerate()
+            .map(|(idx, (date, _))| (*date, idx))
+            .collect();
+        let to_be_deleted: Vec<usize> = police(timestamp, &durations, &indexed)
+            .iter()
+            .map(|item| item.1)
+            .collect();
+
+        Ok(indexed
+            .iter()
+            .map(|(date, idx)| RetentionMark {
+                keep: !to_be_deleted.contains(idx),
+                bucket: RetentionPolicy::duration_bucket(&durations, *timestamp - *date)
+                    .map(|idx| policy[idx].to_string()),
+            })
+            .collect())
+    }
+
+    fn apply_counts<T>(
+        counts: &RetentionCounts,
+        snapshots: &[(DateTime<Utc>, T)],
+    ) -> Vec<RetentionMark> {
+        let mut marks = vec![
+            RetentionMark {
+                keep: false,
+                bucket: None,
+            };
+            snapshots.len()
+        ];
+        // indices of the snapshots ordered from newest to oldest
+        let mut order: Vec<usize> = (0..snapshots.len()).collect();
+
+        order.sort_by_key(|&idx| Reverse(snapshots[idx].0));
+
+        for &idx in order.iter().take(counts.keep_last) {
+            marks[idx].keep = true;
+            marks[idx].bucket = Some(String::from("last"));
+        }
+
+        for (name, count, format) in [
+            ("hourly", counts.hourly, "%Y-%m-%d %H:00"),
+            ("daily", counts.daily, "%Y-%m-%d"),
+            ("weekly", counts.weekly, "%G-W%V"),
+            ("monthly", counts.monthly, "%Y-%m"),
+            ("yearly", counts.yearly, "%Y"),
+        ] {
+            let mut last_period: Option<String> = None;
+            let mut kept = 0;
+
+            for &idx in order.iter() {
+                if kept >= count {
+                    break;
+                }
+
+                let period = snapshots[idx].0.format(format).to_string();
+
+                if last_period.as_ref() != Some(&period) {
+                    kept += 1;
+                    marks[idx].keep = true;
+
+                    if marks[idx].bucket.is_none() {
+                        marks[idx].bucket = Some(format!("{} {}", name, period));
+                    }
+
+                    last_period = Some(period);
+                }
+            }
+        }
+
+        marks
+    }
+
+    /// Get the index of the shortest duration covering the given age
+    ///
+    /// * `durations` - durations of the policy entries
+    /// * `age` - age of the snapshot
+    fn duration_bucket(durations: &[Duration], age: Duration) -> Option<usize> {
+        durations
+            .iter()
+            .enumerate()
+            .filter(|(_, duration)| age <= **duration)
+            .min_by_key(|(_, duration)| **duration)
+            .map(|(idx, _)| idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn read_policy() {
+        let durations: RetentionPolicy =
+            serde_json::from_str(r#"[{ "hours": 1 }, { "months": 2 }]"#).unwrap();
+        let counts: RetentionPolicy =
+            serde_json::from_str(r#"{ "keep_last": 2, "daily": 7 }"#).unwrap();
+
+        assert!(matches!(durations, RetentionPolicy::Durations(d) if d.len() == 2));
+        assert!(matches!(
+            counts,
+            RetentionPolicy::Counts(RetentionCounts {
+                keep_last: 2,
+                daily: 7,
+                hourly: 0,
+                ..
+            })
+        ));
+        assert!(serde_json::from_str::<RetentionPolicy>(r#"{ "dayly": 7 }"#).is_err());
+    }
+
+    #[test]
+    fn read_guards() {
+        let durations: RetentionPolicy =
+            serde_json::from_str(r#"[{ "hours": 1 }, { "months": 2 }]"#).unwrap();
+        let guarded: RetentionPolicy = serde_json::from_str(
+            r#"{ "durations": [{ "hours": 1 }], "min_keep": 3, "max_delete_per_run": 10 }"#,
+        )
+        .unwrap();
+        let counts: RetentionPolicy =
+            serde_json::from_str(r#"{ "daily": 7, "min_keep": 5 }"#).unwrap();
+
+        assert_eq!(durations.min_keep(), None);
+        assert_eq!(durations.max_delete_per_run(), None);
+        assert_eq!(guarded.min_keep(), Some(3));
+        assert_eq!(guarded.max_delete_per_run(), Some(10));
+        assert_eq!(counts.min_keep(), Some(5));
+        assert_eq!(counts.max_delete_per_run(), None);
+        assert!(serde_json::from_str::<RetentionPolicy>(
+            r#"{ "durations": [{ "hours": 1 }], "min_kept": 3 }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn duration_bucket() {
+        let durations = vec![Duration::days(1), Duration::hours(1), Duration::weeks(1)];
+
+        assert_eq!(
+            RetentionPolicy::duration_bucket(&durations, Duration::minutes(5)),
+            Some(1)
+        );
+        assert_eq!(
+            RetentionPolicy::duration_bucket(&durations, Duration::hours(5)),
+            Some(0)
+        );
+        assert_eq!(
+            RetentionPolicy::duration_bucket(&durations, Duration::weeks(2)),
+            None
+        );
+    }
+
+    #[test]
+    fn apply_counts() {
+        let snapshots = vec![
+            (Utc.with_ymd_and_hms(2020, 3, 30, 12, 0, 0).unwrap(), "a"),
+            (Utc.with_ymd_and_hms(2020, 4, 30, 12, 0, 0).unwrap(), "b"),
+            (Utc.with_ymd_and_hms(2020, 5, 9, 8, 0, 0).unwrap(), "c"),
+            (Utc.with_ymd_and_hms(2020, 5, 9, 12, 0, 0).unwrap(), "d"),
+            (Utc.with_ymd_and_hms(2020, 5, 10, 11, 0, 0).unwrap(), "e"),
+            (Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap(), "f"),
+        ];
+        let policy = RetentionPolicy::Counts(RetentionCounts {
+            keep_last: 1,
+            daily: 2,
+            monthly: 2,
+            ..RetentionCounts::default()
+        });
+
+        let marks = policy
+            .apply(
+                &Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap(),
+                &snapshots,
+            )
+            .unwrap();
+
+        assert_eq!(
+            marks.iter().map(|m| m.keep).collect::<Vec<bool>>(),
+            vec![false, true, false, true, false, true]
+        );
+        assert_eq!(marks[1].bucket, Some(String::from("monthly 2020-04")));
+        assert_eq!(marks[3].bucket, Some(String::from("daily 2020-05-09")));
+        assert_eq!(marks[5].bucket, Some(String::from("last")));
+    }
+}
@@ -0,0 +1,189 @@
+use crate::backup_error::BackupError;
+use crate::custom_duration::CustomDuration;
+use crate::objects::{StepOutcome, TransferRecord};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/// # JobState
+///
+/// State of a job persisted between runs in the file `<state directory>/<job name>.json`.
+///
+/// * `last_run` - start time of the last run
+/// * `last_run_end` - end time of the last run
+/// * `last_run_successful` - `true`, if the last run completed successfully
+/// * `last_success` - start time of the last successful run
+/// * `last_error` - error of the last run, if it failed
+/// * `last_error_chain` - error of the last run followed by its causes
+/// * `last_steps` - outcome of every step of the last run
+/// * `last_snapshot` - path of the snapshot created by the last run
+/// * `last_transfers` - size and duration of the transfers of the last run
+/// * `last_bytes_sent` - total size of the transfers of the last run
+/// * `last_deleted_snapshots` - snapshots deleted by the last run (prefixed with the name of the target for snapshots on targets)
+/// * `local_snapshot_count` - number of local snapshots after the last run (only recorded, if metrics are exported)
+/// * `target_snapshot_counts` - number of snapshots on every target after the last run (only recorded, if metrics are exported)
+/// * `failed_runs` - number of failed runs
+/// * `target_failures` - number of runs, in which a step failed for a target, per target
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct JobState {
+    pub last_run: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_run_end: Option<DateTime<Utc>>,
+    pub last_run_successful: bool,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub last_error_chain: Vec<String>,
+    #[serde(default)]
+    pub last_steps: Vec<StepOutcome>,
+    #[serde(default)]
+    pub last_snapshot: Option<String>,
+    #[serde(default)]
+    pub last_transfers: Vec<TransferRecord>,
+    #[serde(default)]
+    pub last_bytes_sent: u64,
+    #[serde(default)]
+    pub last_deleted_snapshots: Vec<String>,
+    #[serde(default)]
+    pub local_snapshot_count: Option<usize>,
+    #[serde(default)]
+    pub target_snapshot_counts: BTreeMap<String, usize>,
+    #[serde(default)]
+    pub failed_runs: u64,
+    #[serde(default)]
+    pub target_failures: BTreeMap<String, u64>,
+}
+
+impl JobState {
+    /// Read the state of a job; returns the default state, if no state was persisted yet
+    ///
+    /// * `state_directory` - directory containing the state files
+    /// * `job_name` - name of the job
+    pub fn read(state_directory: &str, job_name: &str) -> Result<Self, BackupError> {
+        match fs::read_to_string(JobState::path(state_directory, job_name)) {
+            Ok(content) => Ok(serde_json::from_str(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(JobState::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write the state of a job
+    ///
+    /// The state is written to a temporary file first, which is then renamed, so that the state file is never left incomplete.
+    ///
+    /// * `state_directory` - directory containing the state files
+    /// * `job_name` - name of the job
+    pub fn write(&self, state_directory: &str, job_name: &str) -> Result<(), BackupError> {
+        let path = JobState::path(state_directory, job_name);
+        let temp_path = path.with_extension("json.tmp");
+
+        fs::create_dir_all(state_directory)?;
+        fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temp_path, &path)?;
+
+        Ok(())
+    }
+
+    /// Check whether the last successful run is older than the given maximum age (or there was no successful run yet)
+    ///
+    /// * `max_age` - maximum age of the last successful run
+    /// * `timestamp` - timestamp to use as the current moment
+    pub fn is_stale(
+        &self,
+        max_age: &CustomDuration,
+        timestamp: &DateTime<Utc>,
+    ) -> Result<bool, BackupError> {
+        let max_age = max_age.duration_before(timestamp)?;
+
+        Ok(match self.last_success {
+            Some(last_success) => *timestamp - last_success > max_age,
+            None => true,
+        })
+    }
+
+    fn path(state_directory: &str, job_name: &str) -> PathBuf {
+        Path::new(state_directory).join(format!("{}.json", job_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Outcome, TransferStats};
+    use chrono::TimeZone;
+
+    #[test]
+    fn read_missing_state() {
+        let dir = std::env::temp_dir().join("backup-btrfs-test-read-missing-state");
+
+        assert_eq!(
+            JobState::read(dir.to_str().unwrap(), "missing").unwrap(),
+            JobState::default()
+        );
+    }
+
+    #[test]
+    fn write_read_state() {
+        let dir = std::env::temp_dir().join("backup-btrfs-test-write-read-state");
+        let state = JobState {
+            last_run: Some(Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap()),
+            last_run_end: Some(Utc.with_ymd_and_hms(2020, 5, 10, 12, 5, 0).unwrap()),
+            last_run_successful: false,
+            last_success: None,
+            last_error: Some(String::from("failed targets: backup")),
+            last_error_chain: vec![String::from("failed targets: backup")],
+            last_steps: vec![StepOutcome {
+                step: String::from("send"),
+                target: Some(String::from("backup")),
+                outcome: Outcome::Failed,
+                message: Some(String::from("command error")),
+            }],
+            last_snapshot: Some(String::from("/snapshots/2020-05-10T12:00:00Z_test")),
+            last_transfers: vec![TransferRecord {
+                target: String::from("backup"),
+                snapshot: String::from("/snapshots/2020-05-10T12:00:00Z_test"),
+                stats: TransferStats {
+                    bytes: 1024,
+                    seconds: 2.5,
+                },
+            }],
+            last_bytes_sent: 1024,
+            last_deleted_snapshots: vec![String::from("/snapshots/2020-05-01T12:00:00Z_test")],
+            local_snapshot_count: Some(3),
+            target_snapshot_counts: BTreeMap::from([(String::from("backup"), 5)]),
+            failed_runs: 1,
+            target_failures: BTreeMap::from([(String::from("backup"), 1)]),
+        };
+
+        state.write(dir.to_str().unwrap(), "test").unwrap();
+
+        assert_eq!(
+            JobState::read(dir.to_str().unwrap(), "test").unwrap(),
+            state
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn is_stale() {
+        let timestamp = Utc.with_ymd_and_hms(2020, 5, 10, 12, 0, 0).unwrap();
+        let state = JobState {
+            last_success: Some(Utc.with_ymd_and_hms(2020, 5, 10, 9, 0, 0).unwrap()),
+            ..JobState::default()
+        };
+
+        assert!(!state
+            .is_stale(&CustomDuration::hours(4), &timestamp)
+            .unwrap());
+        assert!(state
+            .is_stale(&CustomDuration::hours(2), &timestamp)
+            .unwrap());
+        assert!(JobState::default()
+            .is_stale(&CustomDuration::days(1), &timestamp)
+            .unwrap());
+    }
+}